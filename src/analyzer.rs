@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    disassembler::{successors, EdgeKind},
+    emulator::Address,
+    memory::{MEMORY_SIZE, PROGRAM_START},
+    opcode::Opcode,
+    platform::Platform,
+};
+
+const WORD_SIZE: Address = 2;
+const LONG_LOAD: u16 = 0xF000;
+
+/// Instructions whose behaviour depends on which interpreter a program was written for.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QuirkUse {
+    Shift,
+    LoadStore,
+    JumpOffset,
+}
+
+/// What a static walk over a ROM's reachable code found.
+pub struct Report {
+    pub platform: Platform,
+    pub reasons: Vec<String>,
+    pub extended: BTreeMap<(Platform, &'static str), Vec<Address>>,
+    pub quirks: BTreeMap<QuirkUse, Vec<Address>>,
+    /// Pairs of the instruction pointing I at code and the code it points at.
+    pub self_modifying: Vec<(Address, Address)>,
+    pub unknown: Vec<Address>,
+    /// The patterns of every reachable instruction, e.g. `8XY4`.
+    pub opcodes: BTreeSet<&'static str>,
+}
+
+impl QuirkUse {
+    pub fn description(self) -> &'static str {
+        match self {
+            QuirkUse::Shift => "8XY6/8XYE shifts with VX != VY",
+            QuirkUse::LoadStore => "FX55/FX65 register load and store",
+            QuirkUse::JumpOffset => "BNNN jump with offset",
+        }
+    }
+}
+
+/// Names the SCHIP or XO-CHIP instruction `word` is, if it's one of them.
+fn extension(word: u16) -> Option<(Platform, &'static str)> {
+    let nibbles = (word >> 12, (word >> 8) & 0xF, (word >> 4) & 0xF, word & 0xF);
+
+    let extension = match nibbles {
+        (0x0, 0x0, 0xC, _) => (Platform::SuperChip, "00CN scroll down"),
+        (0x0, 0x0, 0xF, 0xB) => (Platform::SuperChip, "00FB scroll right"),
+        (0x0, 0x0, 0xF, 0xC) => (Platform::SuperChip, "00FC scroll left"),
+        (0x0, 0x0, 0xF, 0xD) => (Platform::SuperChip, "00FD exit"),
+        (0x0, 0x0, 0xF, 0xE) => (Platform::SuperChip, "00FE low resolution"),
+        (0x0, 0x0, 0xF, 0xF) => (Platform::SuperChip, "00FF high resolution"),
+        (0xD, _, _, 0x0) => (Platform::SuperChip, "DXY0 16x16 sprite"),
+        (0xF, _, 0x3, 0x0) => (Platform::SuperChip, "FX30 large font"),
+        (0xF, _, 0x7, 0x5) => (Platform::SuperChip, "FX75 save flags"),
+        (0xF, _, 0x8, 0x5) => (Platform::SuperChip, "FX85 load flags"),
+        (0x0, 0x0, 0xD, _) => (Platform::XoChip, "00DN scroll up"),
+        (0x5, _, _, 0x2) => (Platform::XoChip, "5XY2 save range"),
+        (0x5, _, _, 0x3) => (Platform::XoChip, "5XY3 load range"),
+        (0xF, 0x0, 0x0, 0x0) => (Platform::XoChip, "F000 long I"),
+        (0xF, _, 0x0, 0x1) => (Platform::XoChip, "FN01 plane"),
+        (0xF, 0x0, 0x0, 0x2) => (Platform::XoChip, "F002 audio"),
+        (0xF, _, 0x3, 0xA) => (Platform::XoChip, "FX3A pitch"),
+        _ => return None,
+    };
+
+    Some(extension)
+}
+
+/// Walks the code reachable from the entry point, including SCHIP and XO-CHIP instructions.
+pub fn analyze(program: &[u8]) -> Report {
+    let end = PROGRAM_START as usize + program.len();
+    let word = |address: Address| -> Option<u16> {
+        let offset = (address as usize).checked_sub(PROGRAM_START as usize)?;
+        if address as usize + 1 >= end {
+            return None;
+        }
+
+        Some((program[offset] as u16) << 8 | program[offset + 1] as u16)
+    };
+
+    let mut report = Report {
+        platform: Platform::Chip8,
+        reasons: vec![],
+        extended: BTreeMap::new(),
+        quirks: BTreeMap::new(),
+        self_modifying: vec![],
+        unknown: vec![],
+        opcodes: BTreeSet::new(),
+    };
+
+    let mut visited = BTreeSet::new();
+    let mut pointers = vec![];
+    let mut writes_memory = false;
+
+    let mut pending = vec![PROGRAM_START];
+    while let Some(address) = pending.pop() {
+        if !visited.insert(address) {
+            continue;
+        }
+
+        let instruction = match word(address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+
+        let next = address.checked_add(WORD_SIZE);
+
+        if let Some(extension) = extension(instruction) {
+            report.extended.entry(extension).or_default().push(address);
+            report.opcodes.insert(&extension.1[..4]);
+
+            match instruction {
+                LONG_LOAD => {
+                    if let Some(target) = next.and_then(word) {
+                        pointers.push((address, target));
+                    }
+
+                    pending.extend(next.and_then(|next| next.checked_add(WORD_SIZE)));
+                }
+                0x00FD => {}
+                _ => pending.extend(next),
+            }
+
+            if instruction & 0xF00F == 0x5002 {
+                writes_memory = true;
+            }
+
+            continue;
+        }
+
+        let opcode = match Opcode::from((instruction >> 8) as u8, instruction as u8) {
+            Ok(opcode) => opcode,
+            Err(_) => {
+                report.unknown.push(address);
+                continue;
+            }
+        };
+
+        report.opcodes.insert(opcode.pattern());
+
+        match opcode {
+            Opcode::ShiftRight(first, second) | Opcode::ShiftLeft(first, second) if first != second => {
+                report.quirks.entry(QuirkUse::Shift).or_default().push(address);
+            }
+            Opcode::Dump(_) | Opcode::Load(_) => {
+                report.quirks.entry(QuirkUse::LoadStore).or_default().push(address);
+            }
+            Opcode::JumpOffset(_) => {
+                report.quirks.entry(QuirkUse::JumpOffset).or_default().push(address);
+            }
+            Opcode::SetAddressReg(target) => pointers.push((address, target)),
+            _ => {}
+        }
+
+        if let Opcode::Dump(_) | Opcode::BinaryCodedDecimal(_) = opcode {
+            writes_memory = true;
+        }
+
+        for (kind, target) in successors(address, opcode) {
+            match kind {
+                EdgeKind::Computed => {}
+                // XO-CHIP skips step over the whole of a four byte `F000 NNNN`
+                EdgeKind::Skip if next.and_then(word) == Some(LONG_LOAD) => pending.extend(target.checked_add(WORD_SIZE)),
+                _ => pending.push(target),
+            }
+        }
+    }
+
+    if writes_memory {
+        report.self_modifying = pointers.into_iter()
+            .filter(|(_, target)| visited.contains(target) && word(*target).is_some())
+            .collect();
+    }
+
+    for ((platform, name), _) in report.extended.iter() {
+        if *platform > report.platform {
+            report.platform = *platform;
+        }
+
+        report.reasons.push(format!("uses {} ({})", name, platform));
+    }
+
+    if program.len() > MEMORY_SIZE - PROGRAM_START as usize {
+        report.platform = Platform::XoChip;
+        report.reasons.push(format!("is {} bytes, more than fits in 4K of memory", program.len()));
+    }
+
+    report
+}
+
+pub fn print_report(report: &Report) {
+    println!("Platform: {}", report.platform);
+    for reason in report.reasons.iter() {
+        println!("  {}", reason);
+    }
+
+    if !report.extended.is_empty() {
+        println!();
+        println!("Extended instructions:");
+        for ((platform, name), addresses) in report.extended.iter() {
+            println!("  {:<8} {:<24} {}", platform.to_string(), name, format_addresses(addresses));
+        }
+    }
+
+    if !report.quirks.is_empty() {
+        println!();
+        println!("Quirk-sensitive instructions:");
+        for (quirk, addresses) in report.quirks.iter() {
+            println!("  {:<36} {}", quirk.description(), format_addresses(addresses));
+        }
+    }
+
+    if !report.self_modifying.is_empty() {
+        println!();
+        println!("Likely self-modifying code, I is pointed at instructions that may be overwritten:");
+        for (address, target) in report.self_modifying.iter() {
+            println!("  {:#05x} sets I to {:#05x}", address, target);
+        }
+    }
+
+    if !report.unknown.is_empty() {
+        println!();
+        println!("Unknown instructions reached: {}", format_addresses(&report.unknown));
+    }
+}
+
+fn format_addresses(addresses: &[Address]) -> String {
+    let mut addresses = addresses.to_vec();
+    addresses.sort();
+
+    addresses.iter()
+        .map(|address| format!("{:#05x}", address))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,959 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    debug_map::DebugMap,
+    emulator::{Address, Register},
+    memory::PROGRAM_START,
+    opcode::Opcode,
+};
+
+const MEMORY_END: u32 = 0x1000;
+
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn new(file: Rc<str>, line: usize, column: usize) -> Location {
+        Location { file, line, column }
+    }
+
+    pub fn at_column(&self, column: usize) -> Location {
+        Location::new(self.file.clone(), self.line, column)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub struct AssembleError {
+    pub location: Location,
+    pub message: String,
+}
+
+impl AssembleError {
+    pub fn new(location: &Location, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            location: location.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: error: {}", self.location, self.message)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Clone)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String, Location),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// The part of an already emitted instruction or datum that an expression fills in.
+#[derive(Clone, Copy)]
+pub enum Field {
+    Nibble,
+    Byte,
+    Word,
+    Address,
+    /// The plane mask in the high byte of `plane`/`FN01`.
+    Planes,
+}
+
+enum Symbol {
+    Label(Address),
+    Constant(Expr),
+}
+
+struct Fixup {
+    address: Address,
+    field: Field,
+    expr: Expr,
+    location: Location,
+}
+
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub debug_map: DebugMap,
+}
+
+/// Lays out bytes in memory and resolves symbols for the assembler front-ends.
+///
+/// Expressions are never evaluated eagerly: they are recorded as fixups against the
+/// bytes they belong to and patched once every symbol in the program is known, so
+/// forward references work the same way everywhere.
+pub struct Backend {
+    origin: Address,
+    here: u32,
+    rom: Vec<u8>,
+    symbols: HashMap<String, (Symbol, Location)>,
+    fixups: Vec<Fixup>,
+    location: Location,
+    lines: BTreeMap<Address, Location>,
+    new_line: bool,
+    aliases: BTreeMap<Register, String>,
+    errors: Vec<AssembleError>,
+}
+
+impl Backend {
+    pub fn new(origin: Address) -> Backend {
+        Backend {
+            origin,
+            here: origin as u32,
+            rom: vec![],
+            symbols: HashMap::new(),
+            fixups: vec![],
+            location: Location::new(Rc::from(""), 0, 0),
+            lines: BTreeMap::new(),
+            new_line: false,
+            aliases: BTreeMap::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Sets the source location attributed to everything emitted from here on.
+    pub fn locate(&mut self, location: &Location) {
+        self.location = location.clone();
+        self.new_line = true;
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    pub fn error(&mut self, error: AssembleError) {
+        self.errors.push(error);
+    }
+
+    pub fn here(&self) -> Address {
+        self.here as Address
+    }
+
+    pub fn set_here(&mut self, address: i64) {
+        if address < self.origin as i64 || address >= MEMORY_END as i64 {
+            let message = format!("origin {:#x} is outside of program memory", address);
+            self.error(AssembleError::new(&self.location.clone(), message));
+            return;
+        }
+
+        self.here = address as u32;
+    }
+
+    pub fn define_label(&mut self, name: &str) {
+        let address = self.here();
+        self.define(name, Symbol::Label(address));
+    }
+
+    pub fn define_constant(&mut self, name: &str, expr: Expr) {
+        self.define(name, Symbol::Constant(expr));
+    }
+
+    /// Records a friendlier name for a register so debuggers can show it.
+    pub fn define_alias(&mut self, name: &str, register: Register) {
+        self.aliases.insert(register, name.to_string());
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol) {
+        if let Some((_, previous)) = self.symbols.get(name) {
+            let message = format!("`{}` is already defined at {}", name, previous);
+            self.error(AssembleError::new(&self.location.clone(), message));
+            return;
+        }
+
+        self.symbols.insert(name.to_string(), (symbol, self.location.clone()));
+    }
+
+    /// Whether `size` more bytes fit below the end of memory, reporting it when they don't.
+    fn fits(&mut self, size: u32) -> bool {
+        if self.here + size <= MEMORY_END {
+            return true;
+        }
+
+        let message = format!("program does not fit below {:#x}", MEMORY_END);
+        self.error(AssembleError::new(&self.location.clone(), message));
+        false
+    }
+
+    pub fn emit_byte(&mut self, byte: u8) {
+        if !self.fits(1) {
+            return;
+        }
+
+        let offset = (self.here - self.origin as u32) as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+
+        self.rom[offset] = byte;
+        if self.new_line {
+            self.lines.insert(self.here as Address, self.location.clone());
+            self.new_line = false;
+        }
+
+        self.here += 1;
+    }
+
+    pub fn emit_word(&mut self, word: u16) {
+        if !self.fits(2) {
+            return;
+        }
+
+        self.emit_byte((word >> 8) as u8);
+        self.emit_byte(word as u8);
+    }
+
+    pub fn emit_opcode(&mut self, opcode: Opcode) {
+        if !self.fits(2) {
+            return;
+        }
+
+        // Still taking up the space on failure, so later addresses are where they'd be
+        let bytes = opcode.to_bytes().unwrap_or_else(|message| {
+            self.error(AssembleError::new(&self.location.clone(), message));
+            [0, 0]
+        });
+
+        for byte in bytes.iter() {
+            self.emit_byte(*byte);
+        }
+    }
+
+    /// Emits an opcode whose operand is filled in from `expr` once symbols are resolved.
+    pub fn emit_opcode_with(&mut self, opcode: Opcode, field: Field, expr: Expr) {
+        let address = self.here();
+        if !self.fits(2) {
+            return;
+        }
+
+        self.emit_opcode(opcode);
+        match field {
+            Field::Nibble | Field::Byte => self.fixup(address + 1, field, expr),
+            Field::Word | Field::Address | Field::Planes => self.fixup(address, field, expr),
+        }
+    }
+
+    /// Emits a placeholder the size of `field` which is filled in from `expr` later.
+    pub fn emit_expression(&mut self, field: Field, expr: Expr) {
+        let address = self.here();
+        let size = match field {
+            Field::Nibble | Field::Byte => 1,
+            Field::Word | Field::Address | Field::Planes => 2,
+        };
+        if !self.fits(size) {
+            return;
+        }
+
+        for _ in 0..size {
+            self.emit_byte(0);
+        }
+        self.fixup(address, field, expr);
+    }
+
+    pub fn fixup(&mut self, address: Address, field: Field, expr: Expr) {
+        self.fixups.push(Fixup {
+            address,
+            field,
+            expr,
+            location: self.location.clone(),
+        });
+    }
+
+    pub fn evaluate(&self, expr: &Expr, location: &Location) -> Result<i64, AssembleError> {
+        self.evaluate_with(expr, location, &mut vec![])
+    }
+
+    fn evaluate_with(&self, expr: &Expr, location: &Location, resolving: &mut Vec<String>) -> Result<i64, AssembleError> {
+        match expr {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name, symbol_location) => {
+                match self.symbols.get(name) {
+                    Some((Symbol::Label(address), _)) => Ok(*address as i64),
+                    Some((Symbol::Constant(expr), _)) => {
+                        if resolving.contains(name) {
+                            let message = format!("`{}` is defined in terms of itself", name);
+                            return Err(AssembleError::new(symbol_location, message));
+                        }
+
+                        resolving.push(name.clone());
+                        let value = self.evaluate_with(expr, symbol_location, resolving);
+                        resolving.pop();
+
+                        value
+                    }
+                    None => Err(AssembleError::new(symbol_location, format!("`{}` is not defined", name))),
+                }
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.evaluate_with(operand, location, resolving)?;
+                Ok(match op {
+                    UnaryOp::Negate => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                })
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.evaluate_with(left, location, resolving)?;
+                let right = self.evaluate_with(right, location, resolving)?;
+
+                let value = match op {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Subtract => left.wrapping_sub(right),
+                    BinaryOp::Multiply => left.wrapping_mul(right),
+                    BinaryOp::Divide | BinaryOp::Modulo if right == 0 => {
+                        return Err(AssembleError::new(location, "division by zero"));
+                    }
+                    BinaryOp::Divide => left.wrapping_div(right),
+                    BinaryOp::Modulo => left.wrapping_rem(right),
+                    BinaryOp::And => left & right,
+                    BinaryOp::Or => left | right,
+                    BinaryOp::Xor => left ^ right,
+                    BinaryOp::ShiftLeft => left.wrapping_shl(right as u32),
+                    BinaryOp::ShiftRight => left.wrapping_shr(right as u32),
+                };
+
+                Ok(value)
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Result<Program, Vec<AssembleError>> {
+        let fixups = std::mem::take(&mut self.fixups);
+        let mut resolved = vec![];
+        for fixup in fixups {
+            let value = match self.evaluate(&fixup.expr, &fixup.location) {
+                Ok(value) => value,
+                Err(error) => {
+                    self.error(error);
+                    continue;
+                }
+            };
+
+            let (minimum, maximum, size) = match fixup.field {
+                Field::Nibble => (0, 0xF, 1),
+                Field::Byte => (-0x80, 0xFF, 1),
+                Field::Word => (-0x8000, 0xFFFF, 2),
+                Field::Address => (0, 0xFFF, 2),
+                Field::Planes => (0, 0x3, 2),
+            };
+
+            if value < minimum || value > maximum {
+                let message = format!("value {:#x} does not fit in {}", value, field_name(fixup.field));
+                self.error(AssembleError::new(&fixup.location, message));
+                continue;
+            }
+
+            let offset = (fixup.address - self.origin) as usize;
+            if fixup.address as u32 + size > MEMORY_END || offset + size as usize > self.rom.len() {
+                let message = format!("{} at {:#x} does not fit below {:#x}", field_name(fixup.field), fixup.address, MEMORY_END);
+                self.error(AssembleError::new(&fixup.location, message));
+                continue;
+            }
+
+            // `SYS` is the only address operand whose value can turn it into another instruction
+            if let Field::Address = fixup.field {
+                if self.rom[offset] & 0xF0 == 0 {
+                    if let Err(message) = Opcode::Call(value as Address).encode() {
+                        self.error(AssembleError::new(&fixup.location, message));
+                        continue;
+                    }
+                }
+            }
+
+            resolved.push((offset, fixup.field, value));
+        }
+
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        for (offset, field, value) in resolved {
+            match field {
+                Field::Nibble => self.rom[offset] = (self.rom[offset] & 0xF0) | value as u8,
+                Field::Byte => self.rom[offset] = value as u8,
+                Field::Word => {
+                    self.rom[offset] = (value >> 8) as u8;
+                    self.rom[offset + 1] = value as u8;
+                }
+                Field::Address => {
+                    self.rom[offset] = (self.rom[offset] & 0xF0) | (value >> 8) as u8;
+                    self.rom[offset + 1] = value as u8;
+                }
+                Field::Planes => self.rom[offset] = (self.rom[offset] & 0xF0) | value as u8,
+            }
+        }
+
+        Ok(Program {
+            debug_map: self.debug_map(),
+            rom: self.rom,
+        })
+    }
+
+    fn debug_map(&self) -> DebugMap {
+        let mut debug_map = DebugMap::new();
+
+        let mut labels: Vec<(Address, &String)> = self.symbols.iter()
+            .filter_map(|(name, (symbol, _))| match symbol {
+                Symbol::Label(address) => Some((*address, name)),
+                Symbol::Constant(_) => None,
+            })
+            .collect();
+        labels.sort();
+
+        for (address, name) in labels {
+            debug_map.add_symbol(address, name);
+        }
+
+        for (register, name) in self.aliases.iter() {
+            debug_map.add_alias(*register, name);
+        }
+
+        for (address, location) in self.lines.iter() {
+            debug_map.add_line(*address, &location.file, location.line);
+        }
+
+        debug_map
+    }
+}
+
+fn field_name(field: Field) -> &'static str {
+    match field {
+        Field::Nibble => "a nibble",
+        Field::Byte => "a byte",
+        Field::Word => "a word",
+        Field::Address => "an address",
+        Field::Planes => "a plane mask",
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Text(String),
+    Punct(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Number(value) => write!(f, "`{}`", value),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Punct(punct) => write!(f, "`{}`", punct),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Spanned {
+    token: Token,
+    location: Location,
+}
+
+const PUNCTUATION: [&str; 18] = [
+    "<<", ">>", ",", "(", ")", "[", "]", "+", "-", "*", "/", "%", "&", "|", "^", "~", ":", "=",
+];
+
+fn tokenize(text: &str, location: &Location) -> Result<Vec<Spanned>, AssembleError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let here = location.at_column(i + 1);
+
+        if c == ';' {
+            break;
+        }
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+
+            Token::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() || c == '#' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+
+            let literal: String = chars[start..i].iter().collect();
+            if literal == "$" {
+                Token::Punct("$")
+            } else {
+                Token::Number(parse_number(&literal).ok_or_else(|| {
+                    AssembleError::new(&here, format!("invalid number `{}`", literal))
+                })?)
+            }
+        } else if c == '"' || c == '\'' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+
+            if i == chars.len() {
+                return Err(AssembleError::new(&here, "unterminated string"));
+            }
+
+            let text: String = chars[start + 1..i].iter().collect();
+            i += 1;
+
+            if c == '\'' {
+                let mut characters = text.chars();
+                match (characters.next(), characters.next()) {
+                    (Some(character), None) if character.is_ascii() => Token::Number(character as i64),
+                    _ => return Err(AssembleError::new(&here, "character literals must hold a single ASCII character")),
+                }
+            } else {
+                Token::Text(text)
+            }
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            match PUNCTUATION.iter().find(|punct| rest.starts_with(*punct)) {
+                Some(punct) => {
+                    i += punct.len();
+                    Token::Punct(punct)
+                }
+                None => return Err(AssembleError::new(&here, format!("unexpected character `{}`", c))),
+            }
+        };
+
+        tokens.push(Spanned { token, location: here });
+    }
+
+    Ok(tokens)
+}
+
+pub fn parse_number(literal: &str) -> Option<i64> {
+    let lower = literal.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('#')).or_else(|| lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Parses an expression out of a token stream with conventional operator precedence.
+struct ExprParser<'a> {
+    tokens: &'a [Spanned],
+    position: usize,
+    here: Address,
+    end: Location,
+}
+
+impl<'a> ExprParser<'a> {
+    fn parse(tokens: &'a [Spanned], here: Address, end: Location) -> Result<Expr, AssembleError> {
+        let mut parser = ExprParser { tokens, position: 0, here, end };
+        let expr = parser.binary(0)?;
+
+        match parser.tokens.get(parser.position) {
+            Some(extra) => Err(AssembleError::new(&extra.location, format!("unexpected {}", extra.token))),
+            None => Ok(expr),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, AssembleError> {
+        const LEVELS: [&[(&str, BinaryOp)]; 6] = [
+            &[("|", BinaryOp::Or)],
+            &[("^", BinaryOp::Xor)],
+            &[("&", BinaryOp::And)],
+            &[("<<", BinaryOp::ShiftLeft), (">>", BinaryOp::ShiftRight)],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+            &[("*", BinaryOp::Multiply), ("/", BinaryOp::Divide), ("%", BinaryOp::Modulo)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.tokens.get(self.position) {
+                Some(Spanned { token: Token::Punct(punct), .. }) => {
+                    LEVELS[level].iter().find(|(symbol, _)| symbol == punct).map(|(_, op)| *op)
+                }
+                _ => None,
+            };
+
+            match op {
+                Some(op) => {
+                    self.position += 1;
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                }
+                None => return Ok(left),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, AssembleError> {
+        let spanned = match self.tokens.get(self.position) {
+            Some(spanned) => spanned.clone(),
+            None => return Err(AssembleError::new(&self.end, "expected an expression")),
+        };
+
+        self.position += 1;
+        match spanned.token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Ident(name) => Ok(Expr::Symbol(name, spanned.location)),
+            Token::Punct("$") => Ok(Expr::Number(self.here as i64)),
+            Token::Punct("-") => Ok(Expr::Unary(UnaryOp::Negate, Box::new(self.unary()?))),
+            Token::Punct("~") => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?))),
+            Token::Punct("+") => self.unary(),
+            Token::Punct("(") => {
+                let expr = self.binary(0)?;
+                match self.tokens.get(self.position) {
+                    Some(Spanned { token: Token::Punct(")"), .. }) => {
+                        self.position += 1;
+                        Ok(expr)
+                    }
+                    Some(other) => Err(AssembleError::new(&other.location, format!("expected `)` but found {}", other.token))),
+                    None => Err(AssembleError::new(&self.end, "expected `)`")),
+                }
+            }
+            other => Err(AssembleError::new(&spanned.location, format!("unexpected {}", other))),
+        }
+    }
+}
+
+enum Operand {
+    Register(Register),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    LargeFont,
+    Bcd,
+    Expr(Expr),
+}
+
+/// Assembles the classic (Cowgod) syntax produced by `alvin disassemble`.
+struct Assembler {
+    backend: Backend,
+    includes: Vec<PathBuf>,
+}
+
+pub fn assemble_file(path: &Path) -> Result<Program, Vec<AssembleError>> {
+    let mut assembler = Assembler {
+        backend: Backend::new(PROGRAM_START),
+        includes: vec![],
+    };
+
+    let location = Location::new(Rc::from(path.display().to_string()), 0, 0);
+    assembler.include(path, &location);
+
+    assembler.backend.finish()
+}
+
+impl Assembler {
+    fn include(&mut self, path: &Path, from: &Location) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.includes.contains(&canonical) {
+            self.backend.error(AssembleError::new(from, format!("{} includes itself", path.display())));
+            return;
+        }
+
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                self.backend.error(AssembleError::new(from, format!("could not read {}: {}", path.display(), error)));
+                return;
+            }
+        };
+
+        self.includes.push(canonical);
+
+        let file: Rc<str> = Rc::from(path.display().to_string());
+        for (number, line) in source.lines().enumerate() {
+            let location = Location::new(file.clone(), number + 1, 1);
+            self.backend.locate(&location);
+
+            let result = tokenize(line, &location)
+                .and_then(|tokens| self.statement(&tokens, &location.at_column(line.len() + 1), path));
+            if let Err(error) = result {
+                self.backend.error(error);
+            }
+        }
+
+        self.includes.pop();
+    }
+
+    fn statement(&mut self, tokens: &[Spanned], end: &Location, path: &Path) -> Result<(), AssembleError> {
+        let mut tokens = tokens;
+
+        if let [Spanned { token: Token::Ident(name), .. }, Spanned { token: Token::Punct(":"), .. }, rest @ ..] = tokens {
+            self.backend.define_label(name);
+            tokens = rest;
+        }
+
+        if let [Spanned { token: Token::Ident(name), .. }, Spanned { token, .. }, rest @ ..] = tokens {
+            let is_constant = match token {
+                Token::Ident(directive) => directive.eq_ignore_ascii_case("equ"),
+                Token::Punct("=") => true,
+                _ => false,
+            };
+
+            if is_constant {
+                let expr = ExprParser::parse(rest, self.backend.here(), end.clone())?;
+                self.backend.define_constant(name, expr);
+                return Ok(());
+            }
+        }
+
+        let (mnemonic, operands) = match tokens {
+            [] => return Ok(()),
+            [Spanned { token: Token::Ident(mnemonic), .. }, rest @ ..] => (mnemonic.to_ascii_uppercase(), rest),
+            [other, ..] => return Err(AssembleError::new(&other.location, format!("expected an instruction but found {}", other.token))),
+        };
+
+        let mnemonic_location = &tokens[0].location;
+        match mnemonic.as_str() {
+            "INCLUDE" => {
+                match operands {
+                    [Spanned { token: Token::Text(name), .. }] => {
+                        let included = path.parent().unwrap_or_else(|| Path::new("")).join(name);
+                        self.include(&included, mnemonic_location);
+                        Ok(())
+                    }
+                    _ => Err(AssembleError::new(mnemonic_location, "INCLUDE expects a quoted file name")),
+                }
+            }
+            "DB" | "DW" => {
+                let field = if mnemonic == "DB" { Field::Byte } else { Field::Word };
+                for operand in split_operands(operands) {
+                    match operand {
+                        [Spanned { token: Token::Text(text), .. }] => {
+                            for byte in text.bytes() {
+                                match field {
+                                    Field::Byte => self.backend.emit_byte(byte),
+                                    _ => self.backend.emit_word(byte as u16),
+                                }
+                            }
+                        }
+                        _ => {
+                            let expr = ExprParser::parse(operand, self.backend.here(), end.clone())?;
+                            self.backend.emit_expression(field, expr);
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+            "ORG" => {
+                let expr = ExprParser::parse(operands, self.backend.here(), end.clone())?;
+                let address = self.backend.evaluate(&expr, mnemonic_location)?;
+                self.backend.set_here(address);
+                Ok(())
+            }
+            _ => {
+                let mut parsed = vec![];
+                for operand in split_operands(operands) {
+                    parsed.push(self.operand(operand, end)?);
+                }
+
+                self.instruction(&mnemonic, parsed, mnemonic_location)
+            }
+        }
+    }
+
+    fn operand(&self, tokens: &[Spanned], end: &Location) -> Result<Operand, AssembleError> {
+        if let [Spanned { token: Token::Ident(name), .. }] = tokens {
+            let operand = match name.to_ascii_uppercase().as_str() {
+                "I" => Some(Operand::I),
+                "DT" => Some(Operand::DelayTimer),
+                "ST" => Some(Operand::SoundTimer),
+                "K" => Some(Operand::Key),
+                "F" => Some(Operand::Font),
+                "HF" => Some(Operand::LargeFont),
+                "B" => Some(Operand::Bcd),
+                register => parse_register(register).map(Operand::Register),
+            };
+
+            if let Some(operand) = operand {
+                return Ok(operand);
+            }
+        }
+
+        if let [Spanned { token: Token::Punct("["), .. }, Spanned { token: Token::Ident(name), .. }, Spanned { token: Token::Punct("]"), .. }] = tokens {
+            if name.eq_ignore_ascii_case("i") {
+                return Ok(Operand::IndirectI);
+            }
+        }
+
+        let end = tokens.last().map(|last| last.location.clone()).unwrap_or_else(|| end.clone());
+        ExprParser::parse(tokens, self.backend.here(), end).map(Operand::Expr)
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: Vec<Operand>, location: &Location) -> Result<(), AssembleError> {
+        use self::Operand::*;
+
+        let backend = &mut self.backend;
+        backend.locate(location);
+        match (mnemonic, operands.as_slice()) {
+            ("CLS", []) => backend.emit_opcode(Opcode::Clear),
+            ("RET", []) => backend.emit_opcode(Opcode::Return),
+            ("LOW", []) => backend.emit_opcode(Opcode::LowResolution),
+            ("HIGH", []) => backend.emit_opcode(Opcode::HighResolution),
+            ("SCD", [Expr(rows)]) => backend.emit_opcode_with(Opcode::ScrollDown(0), Field::Nibble, rows.clone()),
+            ("SCU", [Expr(rows)]) => backend.emit_opcode_with(Opcode::ScrollUp(0), Field::Nibble, rows.clone()),
+            ("SCR", []) => backend.emit_opcode(Opcode::ScrollRight),
+            ("SCL", []) => backend.emit_opcode(Opcode::ScrollLeft),
+            ("PLANE", [Expr(planes)]) => backend.emit_opcode_with(Opcode::SelectPlanes(0), Field::Planes, planes.clone()),
+            ("SYS", [Expr(target)]) => backend.emit_opcode_with(Opcode::Call(0), Field::Address, target.clone()),
+            ("JP", [Expr(target)]) => backend.emit_opcode_with(Opcode::Goto(0), Field::Address, target.clone()),
+            ("JP", [Register(0), Expr(target)]) => backend.emit_opcode_with(Opcode::JumpOffset(0), Field::Address, target.clone()),
+            ("CALL", [Expr(target)]) => backend.emit_opcode_with(Opcode::CallFunction(0), Field::Address, target.clone()),
+            ("SE", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::SkipEqReg(*x, *y)),
+            ("SE", [Register(x), Expr(value)]) => backend.emit_opcode_with(Opcode::SkipEq(*x, 0), Field::Byte, value.clone()),
+            ("SNE", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::SkipNEqReg(*x, *y)),
+            ("SNE", [Register(x), Expr(value)]) => backend.emit_opcode_with(Opcode::SkipNEq(*x, 0), Field::Byte, value.clone()),
+            ("LD", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::Copy(*x, *y)),
+            ("LD", [Register(x), Expr(value)]) => backend.emit_opcode_with(Opcode::Set(*x, 0), Field::Byte, value.clone()),
+            ("LD", [I, Expr(target)]) => backend.emit_opcode_with(Opcode::SetAddressReg(0), Field::Address, target.clone()),
+            ("LD", [Register(x), DelayTimer]) => backend.emit_opcode(Opcode::StoreDelayTimer(*x)),
+            ("LD", [Register(x), Key]) => backend.emit_opcode(Opcode::StoreKeypress(*x)),
+            ("LD", [DelayTimer, Register(x)]) => backend.emit_opcode(Opcode::SetDelayTimer(*x)),
+            ("LD", [SoundTimer, Register(x)]) => backend.emit_opcode(Opcode::SetSoundTimer(*x)),
+            ("LD", [Font, Register(x)]) => backend.emit_opcode(Opcode::StoreSpriteAddress(*x)),
+            ("LD", [LargeFont, Register(x)]) => backend.emit_opcode(Opcode::StoreLargeSpriteAddress(*x)),
+            ("LD", [Bcd, Register(x)]) => backend.emit_opcode(Opcode::BinaryCodedDecimal(*x)),
+            ("LD", [IndirectI, Register(x)]) => backend.emit_opcode(Opcode::Dump(*x)),
+            ("LD", [Register(x), IndirectI]) => backend.emit_opcode(Opcode::Load(*x)),
+            ("ADD", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::AddAssignReg(*x, *y)),
+            ("ADD", [Register(x), Expr(value)]) => backend.emit_opcode_with(Opcode::AddAssign(*x, 0), Field::Byte, value.clone()),
+            ("ADD", [I, Register(x)]) => backend.emit_opcode(Opcode::IncrementAddressReg(*x)),
+            ("OR", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::Or(*x, *y)),
+            ("AND", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::And(*x, *y)),
+            ("XOR", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::Xor(*x, *y)),
+            ("SUB", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::SubAssignReg(*x, *y)),
+            ("SUBN", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::Subtract(*x, *y)),
+            ("SHR", [Register(x)]) => backend.emit_opcode(Opcode::ShiftRight(*x, *x)),
+            ("SHR", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::ShiftRight(*x, *y)),
+            ("SHL", [Register(x)]) => backend.emit_opcode(Opcode::ShiftLeft(*x, *x)),
+            ("SHL", [Register(x), Register(y)]) => backend.emit_opcode(Opcode::ShiftLeft(*x, *y)),
+            ("RND", [Register(x), Expr(value)]) => backend.emit_opcode_with(Opcode::SetRand(*x, 0), Field::Byte, value.clone()),
+            ("DRW", [Register(x), Register(y), Expr(height)]) => backend.emit_opcode_with(Opcode::Draw(*x, *y, 0), Field::Nibble, height.clone()),
+            ("SKP", [Register(x)]) => backend.emit_opcode(Opcode::SkipKeyPress(*x)),
+            ("SKNP", [Register(x)]) => backend.emit_opcode(Opcode::SkipNoKeyPress(*x)),
+            ("CLS", _) | ("RET", _) | ("LOW", _) | ("HIGH", _) | ("SCD", _) | ("SCU", _) | ("SCR", _) | ("SCL", _) | ("PLANE", _) | ("SYS", _) | ("JP", _) | ("CALL", _) | ("SE", _) | ("SNE", _) |
+            ("LD", _) | ("ADD", _) | ("OR", _) | ("AND", _) | ("XOR", _) | ("SUB", _) | ("SUBN", _) |
+            ("SHR", _) | ("SHL", _) | ("RND", _) | ("DRW", _) | ("SKP", _) | ("SKNP", _) => {
+                return Err(AssembleError::new(location, format!("invalid operands for {}", mnemonic)));
+            }
+            _ => return Err(AssembleError::new(location, format!("unknown instruction `{}`", mnemonic))),
+        }
+
+        Ok(())
+    }
+}
+
+fn split_operands(tokens: &[Spanned]) -> Vec<&[Spanned]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+
+    tokens.split(|spanned| spanned.token == Token::Punct(",")).collect()
+}
+
+pub fn parse_register(name: &str) -> Option<Register> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|value| value as Register),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn assemble(name: &str, source: &str) -> Result<Program, Vec<AssembleError>> {
+        let path = env::temp_dir().join(format!("alvin-assembler-{}-{}.asm", process::id(), name));
+        fs::write(&path, source).unwrap();
+
+        let program = assemble_file(&path);
+        fs::remove_file(&path).unwrap();
+        program
+    }
+
+    fn assemble_errors(name: &str, source: &str) -> Vec<AssembleError> {
+        match assemble(name, source) {
+            Ok(_) => panic!("`{}` assembled", source),
+            Err(errors) => errors,
+        }
+    }
+
+    #[test]
+    fn instructions_past_the_end_of_memory_are_errors() {
+        let errors = assemble_errors("straddling", "ORG 0xFFF\nJP 0x200\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("does not fit below 0x1000"));
+
+        let errors = assemble_errors("past", "ORG 0xFFE\nJP 0x200\nJP 0x200\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].location.line, 3);
+    }
+
+    #[test]
+    fn plane_masks_can_use_later_constants() {
+        let program = assemble("plane", "PLANE BOTH\nBOTH = 3\n").unwrap();
+        assert_eq!(program.rom, vec![0xF3, 0x01]);
+
+        let errors = assemble_errors("bad-plane", "PLANE 4\n");
+        assert!(errors[0].message.contains("a plane mask"));
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    fs::File,
+    path::Path,
+    rc::Rc,
+};
+
+use serde_json::Value;
+
+use crate::{
+    octo::compile,
+    platform::Platform,
+    rom_db::RomEntry,
+};
+
+pub const GIF_MAGIC: &[u8] = b"GIF8";
+
+const LENGTH_BYTES: usize = 4;
+
+/// Extracts the program and options from an Octo cartridge, compiling the program to a ROM.
+///
+/// Cartridges hide their payload in the low two bits of every pixel's palette index, most
+/// significant bits first across each frame in turn. The payload is a big endian length
+/// followed by that many bytes of JSON, `{"program": "<Octo source>", "options": {...}}`.
+pub fn load(path: &Path) -> Result<(Vec<u8>, RomEntry), String> {
+    let payload = extract_payload(path)?;
+
+    let cartridge: Value = serde_json::from_slice(&payload)
+        .map_err(|error| format!("the cartridge payload is not valid JSON: {}", error))?;
+    let source = cartridge["program"].as_str().ok_or("the cartridge has no program")?;
+
+    let file: Rc<str> = Rc::from(path.display().to_string());
+    let program = compile(source, file).map_err(|errors| {
+        let errors: Vec<_> = errors.iter().map(|error| error.to_string()).collect();
+        format!("the cartridge program does not compile:\n{}", errors.join("\n"))
+    })?;
+
+    Ok((program.rom, parse_options(&cartridge["options"])))
+}
+
+fn extract_payload(path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|error| error.to_string())?;
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(file).map_err(|error| error.to_string())?;
+
+    let mut bytes = vec![];
+    let mut byte = 0u8;
+    let mut bits = 0;
+    while let Some(frame) = decoder.read_next_frame().map_err(|error| error.to_string())? {
+        for index in frame.buffer.iter() {
+            byte = (byte << 2) | (index & 0x3);
+            bits += 2;
+
+            if bits == 8 {
+                bytes.push(byte);
+                bits = 0;
+            }
+        }
+    }
+
+    if bytes.len() < LENGTH_BYTES {
+        return Err("the image holds no cartridge payload".to_string());
+    }
+
+    let length = bytes[..LENGTH_BYTES].iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+    bytes.get(LENGTH_BYTES..LENGTH_BYTES + length)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| "the cartridge payload is truncated".to_string())
+}
+
+/// Translates Octo's options into the settings alvin keeps for ROMs.
+fn parse_options(options: &Value) -> RomEntry {
+    let flag = |key: &str| options[key].as_bool();
+
+    let platform = match options["maxSize"].as_u64() {
+        Some(size) if size > Platform::SuperChip.program_space() as u64 => Some(Platform::XoChip),
+        Some(size) if size > Platform::Chip8.program_space() as u64 => Some(Platform::SuperChip),
+        Some(_) => Some(Platform::Chip8),
+        None => None,
+    };
+
+    let mut quirks = platform.unwrap_or(Platform::Chip8).quirks();
+    // Octo names the quirks after the SCHIP behaviour, so a set flag turns ours off
+    if let Some(shift) = flag("shiftQuirks") {
+        quirks.shift_uses_vy = !shift;
+    }
+    if let Some(load_store) = flag("loadStoreQuirks") {
+        quirks.load_store_increments_i = !load_store;
+    }
+    if let Some(jump) = flag("jumpQuirks") {
+        quirks.jump_uses_vx = jump;
+    }
+    if let Some(logic) = flag("logicQuirks") {
+        quirks.logic_resets_vf = logic;
+    }
+    if let Some(clip) = flag("clipQuirks") {
+        quirks.wrap_sprites = !clip;
+    }
+
+    let palette = ["backgroundColor", "fillColor", "fillColor2", "blendColor"].iter()
+        .map_while(|key| options[*key].as_str())
+        .map(str::to_string)
+        .collect();
+
+    let keys = options["keys"].as_object()
+        .map(|keys| {
+            keys.iter()
+                .filter_map(|(key, keyboard)| {
+                    let key = u8::from_str_radix(key, 16).ok().filter(|key| *key <= 0xF)?;
+                    Some((key, keyboard.as_str()?.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    RomEntry {
+        title: None,
+        author: None,
+        description: None,
+        platform,
+        quirks: Some(quirks),
+        speed: options["tickrate"].as_u64().map(|speed| speed as u32),
+        keys,
+        palette,
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Write,
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::{
+    debug_map::DebugMap,
+    disassembler::{successors, Analysis, EdgeKind},
+    emulator::Address,
+    memory::PROGRAM_START,
+    opcode::{hex_address, Opcode, Syntax},
+};
+
+const WORD_SIZE: Address = 2;
+
+/// A straight run of instructions which is only entered at the top and only left at the bottom.
+struct Block {
+    instructions: Vec<(Address, Opcode)>,
+    edges: Vec<(EdgeKind, Address)>,
+}
+
+impl Block {
+    fn last(&self) -> Opcode {
+        self.instructions.last().unwrap().1
+    }
+}
+
+pub fn export(buffer: &[u8], syntax: Syntax, symbols: &DebugMap, path: &Path) -> io::Result<()> {
+    let mut analysis = Analysis::new(buffer, PROGRAM_START);
+    analysis.name_labels(symbols);
+
+    fs::write(path, render(&analysis, syntax))
+}
+
+/// Renders the control-flow graph of an analysed program as a Graphviz digraph.
+///
+/// Every subroutine becomes a cluster holding the blocks reachable from its entry
+/// without following calls, and returns are drawn back to each of its call sites.
+pub fn render(analysis: &Analysis, syntax: Syntax) -> String {
+    let blocks = basic_blocks(analysis);
+    let owners = assign_owners(analysis, &blocks);
+    let name = |address: Address| analysis.label(address).unwrap_or_else(|| hex_address(address));
+
+    let mut call_sites: BTreeMap<Address, Vec<Address>> = BTreeMap::new();
+    for (_, block) in blocks.iter() {
+        for (kind, target) in block.edges.iter() {
+            if *kind == EdgeKind::Call {
+                let (address, _) = *block.instructions.last().unwrap();
+                call_sites.entry(*target).or_default().push(address + WORD_SIZE);
+            }
+        }
+    }
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph cfg {{").unwrap();
+    writeln!(dot, "  node [shape=box fontname=monospace];").unwrap();
+
+    let mut clusters: BTreeMap<Option<Address>, Vec<Address>> = BTreeMap::new();
+    for (leader, owner) in owners.iter() {
+        clusters.entry(*owner).or_default().push(*leader);
+    }
+
+    for (owner, leaders) in clusters.iter() {
+        let indent = match owner {
+            Some(entry) => {
+                writeln!(dot, "  subgraph cluster_{:03x} {{", entry).unwrap();
+                writeln!(dot, "    label=\"{}\";", escape(&name(*entry))).unwrap();
+                "    "
+            }
+            None => "  ",
+        };
+
+        for leader in leaders.iter() {
+            let block = &blocks[leader];
+
+            let mut text = format!("{}\\l", escape(&name(*leader)));
+            for (address, opcode) in block.instructions.iter() {
+                text.push_str(&format!("{:#05x}  {}\\l", address, escape(&opcode.render(syntax, &name))));
+            }
+
+            writeln!(dot, "{}{} [label=\"{}\"];", indent, node(*leader), text).unwrap();
+        }
+
+        if owner.is_some() {
+            writeln!(dot, "  }}").unwrap();
+        }
+    }
+
+    let mut external = BTreeSet::new();
+    for (leader, block) in blocks.iter() {
+        for (kind, target) in block.edges.iter() {
+            if !blocks.contains_key(target) {
+                external.insert(*target);
+            }
+
+            let style = match kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::Skip => " [label=\"skip\" color=darkgreen]",
+                EdgeKind::Jump => " [style=bold]",
+                EdgeKind::Call => " [label=\"call\" style=dashed color=blue]",
+                EdgeKind::Computed => " [label=\"computed\" style=dotted color=red]",
+            };
+
+            writeln!(dot, "  {} -> {}{};", node(*leader), node(*target), style).unwrap();
+        }
+
+        if let Opcode::Return = block.last() {
+            let subroutine = owners[leader];
+            let sites = subroutine.and_then(|entry| call_sites.get(&entry)).cloned().unwrap_or_default();
+
+            for site in sites.into_iter().filter(|site| blocks.contains_key(site)) {
+                writeln!(dot, "  {} -> {} [label=\"return\" style=dashed color=gray];", node(*leader), node(site)).unwrap();
+            }
+        }
+    }
+
+    for address in external {
+        writeln!(dot, "  {} [label=\"{}\" shape=plaintext];", node(address), escape(&name(address))).unwrap();
+    }
+
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+/// Splits the decoded instructions into blocks at every branch and branch target.
+fn basic_blocks(analysis: &Analysis) -> BTreeMap<Address, Block> {
+    let mut leaders = BTreeSet::new();
+    leaders.insert(analysis.origin());
+
+    for (address, opcode) in analysis.instructions() {
+        let edges = successors(address, opcode);
+        let falls_through = edges == [(EdgeKind::Fallthrough, address + WORD_SIZE)];
+
+        if !falls_through {
+            leaders.extend(edges.iter().map(|(_, target)| *target));
+            leaders.insert(address + WORD_SIZE);
+        }
+
+        // Nothing falls into code which follows data, so it must be reached some other way
+        let previous = address.checked_sub(WORD_SIZE).and_then(|previous| analysis.instruction(previous));
+        if previous.is_none() {
+            leaders.insert(address);
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for leader in leaders.iter().filter(|leader| analysis.instruction(**leader).is_some()) {
+        let mut instructions = vec![];
+        let mut address = *leader;
+
+        loop {
+            let opcode = analysis.instruction(address).unwrap();
+            instructions.push((address, opcode));
+
+            let edges = successors(address, opcode);
+            let next = address + WORD_SIZE;
+            let continues = edges == [(EdgeKind::Fallthrough, next)]
+                && !leaders.contains(&next)
+                && analysis.instruction(next).is_some();
+
+            if !continues {
+                let edges = edges.into_iter()
+                    .filter(|(kind, target)| *kind != EdgeKind::Fallthrough || analysis.instruction(*target).is_some())
+                    .collect();
+
+                blocks.insert(*leader, Block { instructions, edges });
+                break;
+            }
+
+            address = next;
+        }
+    }
+
+    blocks
+}
+
+/// Decides which subroutine each block belongs to, `None` being the main program.
+fn assign_owners(analysis: &Analysis, blocks: &BTreeMap<Address, Block>) -> BTreeMap<Address, Option<Address>> {
+    let mut entries: Vec<Option<Address>> = blocks.values()
+        .flat_map(|block| block.edges.iter())
+        .filter(|(kind, target)| *kind == EdgeKind::Call && blocks.contains_key(target))
+        .map(|(_, target)| Some(*target))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    entries.push(None);
+
+    let mut owners = BTreeMap::new();
+    for entry in entries {
+        let mut pending: VecDeque<Address> = VecDeque::new();
+        pending.push_back(entry.unwrap_or_else(|| analysis.origin()));
+
+        while let Some(leader) = pending.pop_front() {
+            if owners.contains_key(&leader) || !blocks.contains_key(&leader) {
+                continue;
+            }
+
+            owners.insert(leader, entry);
+
+            for (kind, target) in blocks[&leader].edges.iter() {
+                if *kind != EdgeKind::Call {
+                    pending.push_back(*target);
+                }
+            }
+        }
+    }
+
+    // Anything the walks above missed stays with the main program
+    for leader in blocks.keys() {
+        owners.entry(*leader).or_insert(None);
+    }
+
+    owners
+}
+
+fn node(address: Address) -> String {
+    format!("n{:03x}", address)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
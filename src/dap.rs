@@ -0,0 +1,756 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    analyzer::analyze,
+    assembler::{assemble_file, parse_number, parse_register, AssembleError, Program},
+    debug_map::DebugMap,
+    debugger::{Debugger, StopReason},
+    emulator::{Address, System},
+    font::Font,
+    input::InputAction,
+    loader,
+    memory::{Layout, MAX_MEMORY_SIZE, PROGRAM_START},
+    octo::compile_file,
+    platform::{Platform, Quirks},
+};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const TIMERS_REFERENCE: u64 = 2;
+const POLL_RATE: Duration = Duration::from_millis(16);
+
+/// A Debug Adapter Protocol server, letting editors drive the emulator over stdio.
+pub struct Server<W: Write> {
+    output: W,
+    sequence: u64,
+    system: Option<System>,
+    debugger: Debugger,
+    stop_on_entry: bool,
+    finished: bool,
+    /// How a launched program's system is built, so tests can run without a window.
+    create_system: fn(&[u8], Layout, Font) -> System,
+}
+
+/// Everything needed to start a launched program, gathered before any window is opened.
+struct Launch {
+    rom: Vec<u8>,
+    debug_map: DebugMap,
+    platform: Platform,
+    quirks: Quirks,
+    name: Option<String>,
+}
+
+/// Serves a single debug session over stdin and stdout.
+pub fn serve() -> io::Result<()> {
+    let messages = spawn_reader(BufReader::new(io::stdin()));
+    Server::new(io::stdout()).run(messages)
+}
+
+/// Reads protocol messages on a separate thread so the emulator can keep running in between.
+pub fn spawn_reader<R: BufRead + Send + 'static>(mut reader: R) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(output: W) -> Server<W> {
+        Server {
+            output,
+            sequence: 0,
+            system: None,
+            debugger: Debugger::new(),
+            stop_on_entry: false,
+            finished: false,
+            create_system: System::new,
+        }
+    }
+
+    pub fn run(&mut self, messages: Receiver<Value>) -> io::Result<()> {
+        while !self.finished {
+            let message = if self.debugger.is_running() {
+                messages.try_recv().map_err(|error| error == TryRecvError::Disconnected)
+            } else {
+                messages.recv_timeout(POLL_RATE).map_err(|error| error == RecvTimeoutError::Disconnected)
+            };
+
+            match message {
+                Ok(message) => self.handle(&message)?,
+                Err(true) => break,
+                Err(false) => {}
+            }
+
+            self.run_system()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_system(&mut self) -> io::Result<()> {
+        let system = match self.system.as_mut() {
+            Some(system) => system,
+            None => return Ok(()),
+        };
+
+        if let Some(InputAction::Quit) = system.poll_input() {
+            self.finished = true;
+            return self.event("terminated", json!({}));
+        }
+
+        if !self.debugger.is_running() {
+            return Ok(());
+        }
+
+        for _ in 0..system.speed() {
+            if let Some(reason) = self.debugger.advance(system) {
+                return self.stopped(reason);
+            }
+        }
+
+        system.tick();
+        Ok(())
+    }
+
+    fn handle(&mut self, message: &Value) -> io::Result<()> {
+        if message["type"] != "request" {
+            return Ok(());
+        }
+
+        let command = message["command"].as_str().unwrap_or("");
+        let arguments = &message["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsEvaluateForHovers": true,
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.respond(message, Ok(json!({})))?;
+                    return self.stopped(StopReason::Entry);
+                }
+
+                self.debugger.resume();
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.with_system(|system| Ok(stack_trace(system))),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Timers", "variablesReference": TIMERS_REFERENCE, "expensive": false },
+                ]
+            })),
+            "variables" => self.with_system(|system| Ok(variables(system, arguments["variablesReference"].as_u64()))),
+            "evaluate" => self.with_system(|system| evaluate(system, arguments["expression"].as_str().unwrap_or(""))),
+            "readMemory" => self.with_system(|system| read_memory(system, arguments)),
+            "continue" => {
+                self.debugger.resume();
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let debugger = &mut self.debugger;
+                match self.system.as_ref() {
+                    Some(system) => {
+                        match command {
+                            "next" => debugger.step_over(system),
+                            "stepOut" => debugger.step_out(system),
+                            _ => debugger.step_in(),
+                        }
+
+                        Ok(json!({}))
+                    }
+                    None => Err("no program has been launched".to_string()),
+                }
+            }
+            "pause" => {
+                self.debugger.pause();
+                self.respond(message, Ok(json!({})))?;
+                return self.stopped(StopReason::Pause);
+            }
+            "disconnect" | "terminate" => {
+                self.finished = true;
+                self.respond(message, Ok(json!({})))?;
+                return self.event("terminated", json!({}));
+            }
+            other => Err(format!("`{}` is not supported", other)),
+        };
+
+        self.respond(message, result)?;
+
+        if command == "launch" && self.system.is_some() {
+            self.event("initialized", json!({}))?;
+        }
+
+        Ok(())
+    }
+
+    fn with_system<F>(&self, f: F) -> Result<Value, String>
+        where F: FnOnce(&System) -> Result<Value, String> {
+        match self.system.as_ref() {
+            Some(system) => f(system),
+            None => Err("no program has been launched".to_string()),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let launch = prepare_launch(arguments)?;
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+
+        let platform = launch.platform;
+        let mut system = (self.create_system)(&launch.rom, Layout::for_platform(platform), Font::for_platform(platform));
+        system.set_debug_map(launch.debug_map);
+        if let Some(name) = launch.name {
+            system.set_name(&name);
+        }
+        system.set_quirks(launch.quirks);
+        self.system = Some(system);
+
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let source = arguments["source"]["path"].as_str().ok_or("breakpoints must be set on a source path")?;
+        let lines: Vec<usize> = arguments["breakpoints"].as_array()
+            .map(|breakpoints| breakpoints.iter().filter_map(|breakpoint| breakpoint["line"].as_u64()).map(|line| line as usize).collect())
+            .unwrap_or_default();
+
+        let mut addresses = vec![];
+        let mut breakpoints = vec![];
+        for line in lines {
+            let resolved = self.system.as_ref()
+                .and_then(|system| system.debug_map().resolve_line(Path::new(source), line));
+
+            match resolved {
+                Some((address, actual_line)) => {
+                    addresses.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": actual_line,
+                        "instructionReference": format!("{:#05x}", address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no code was generated for this line",
+                })),
+            }
+        }
+
+        self.debugger.set_source_breakpoints(source, addresses);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let mut addresses = vec![];
+        let mut breakpoints = vec![];
+
+        for breakpoint in arguments["breakpoints"].as_array().cloned().unwrap_or_default() {
+            let reference = breakpoint["instructionReference"].as_str().and_then(parse_address);
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+
+            match reference {
+                Some(address) => {
+                    let address = (address as i64 + offset) as Address;
+                    addresses.push(address);
+                    breakpoints.push(json!({ "verified": true, "instructionReference": format!("{:#05x}", address) }));
+                }
+                None => breakpoints.push(json!({ "verified": false, "message": "invalid instruction reference" })),
+            }
+        }
+
+        self.debugger.set_instruction_breakpoints(addresses);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stopped(&mut self, reason: StopReason) -> io::Result<()> {
+        let reason = match reason {
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+            StopReason::Pause => "pause",
+            StopReason::Exception => "exception",
+        };
+
+        self.event("stopped", json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        }))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.sequence += 1;
+        message["seq"] = json!(self.sequence);
+
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+}
+
+/// Builds the program a launch request asks for, along with its symbols and how to run it.
+fn prepare_launch(arguments: &Value) -> Result<Launch, String> {
+    let program = arguments["program"].as_str().ok_or("`program` must be set to the ROM or source to debug")?;
+    let map = arguments["map"].as_str();
+
+    let (rom, mut debug_map, quirks) = load(Path::new(program), map)?;
+
+    if let Some(symbols) = arguments["symbols"].as_str() {
+        debug_map.load_symbols(Path::new(symbols))
+            .map_err(|error| format!("could not load symbols {}: {}", symbols, error))?;
+    }
+
+    let platform = analyze(&rom).platform;
+    let layout = Layout::for_platform(platform);
+    if rom.len() > layout.program_space() {
+        return Err(format!(
+            "the ROM is {} bytes, but only {} bytes fit from {:#05x}",
+            rom.len(), layout.program_space(), layout.load_address
+        ));
+    }
+
+    Ok(Launch {
+        quirks: quirks.unwrap_or_else(|| platform.quirks()),
+        name: Path::new(program).file_stem().map(|name| name.to_string_lossy().to_string()),
+        rom,
+        debug_map,
+        platform,
+    })
+}
+
+/// Builds or reads the program at `path`, along with the quirks it asks for if it's an Octo cartridge.
+fn load(path: &Path, map: Option<&str>) -> Result<(Vec<u8>, DebugMap, Option<Quirks>), String> {
+    let describe = |errors: Vec<AssembleError>| {
+        errors.iter().map(|error| error.to_string()).collect::<Vec<_>>().join("\n")
+    };
+
+    let built: Option<Result<Program, Vec<AssembleError>>> = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("8o") => Some(compile_file(path)),
+        Some("asm") | Some("s") => Some(assemble_file(path)),
+        _ => None,
+    };
+
+    if let Some(built) = built {
+        let program = built.map_err(describe)?;
+        return Ok((program.rom, program.debug_map, None));
+    }
+
+    let rom = loader::load(path, MAX_MEMORY_SIZE, PROGRAM_START).map_err(|error| error.to_string())?;
+    let quirks = rom.settings.and_then(|settings| settings.quirks);
+    let rom = rom.bytes;
+
+    let map_path = map.map(Path::new).map(Path::to_path_buf).unwrap_or_else(|| path.with_extension("map"));
+    let debug_map = if map.is_some() || map_path.exists() {
+        DebugMap::load(&map_path).map_err(|error| format!("could not load {}: {}", map_path.display(), error))?
+    } else {
+        DebugMap::new()
+    };
+
+    Ok((rom, debug_map, quirks))
+}
+
+fn stack_trace(system: &System) -> Value {
+    let debug_map = system.debug_map();
+
+    let frame = |id: usize, address: Address| {
+        let name = debug_map.symbolize(address).unwrap_or_else(|| format!("{:#05x}", address));
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("{:#05x}", address),
+        });
+
+        if let Some(source) = debug_map.source_line(address) {
+            let file_name = Path::new(&source.file).file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| source.file.clone());
+
+            frame["source"] = json!({ "name": file_name, "path": source.file });
+            frame["line"] = json!(source.line);
+            frame["column"] = json!(1);
+        }
+
+        frame
+    };
+
+    let mut frames = vec![frame(0, system.program_counter())];
+    for (depth, call) in system.call_stack().iter().enumerate() {
+        frames.push(frame(depth + 1, *call));
+    }
+
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+fn variables(system: &System, reference: Option<u64>) -> Value {
+    let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+    let variables = match reference {
+        Some(REGISTERS_REFERENCE) => {
+            let mut variables: Vec<Value> = system.registers().iter().enumerate()
+                .map(|(register, value)| {
+                    let name = match system.debug_map().alias(register as u8) {
+                        Some(alias) => format!("V{:X} ({})", register, alias),
+                        None => format!("V{:X}", register),
+                    };
+
+                    variable(name, format!("{:#04x} ({})", value, value))
+                })
+                .collect();
+
+            let mut address_register = variable("I".to_string(), format!("{:#05x}", system.address_register()));
+            address_register["memoryReference"] = json!(format!("{:#05x}", system.address_register()));
+            variables.push(address_register);
+
+            variables.push(variable("PC".to_string(), format!("{:#05x}", system.program_counter())));
+            variables.push(variable("SP".to_string(), system.call_stack().len().to_string()));
+            variables
+        }
+        Some(TIMERS_REFERENCE) => vec![
+            variable("DT".to_string(), system.delay_timer().to_string()),
+            variable("ST".to_string(), system.sound_timer().to_string()),
+        ],
+        _ => vec![],
+    };
+
+    json!({ "variables": variables })
+}
+
+fn evaluate(system: &System, expression: &str) -> Result<Value, String> {
+    let expression = expression.trim();
+    let result = |value: String| Ok(json!({ "result": value, "variablesReference": 0 }));
+
+    match expression.to_ascii_uppercase().as_str() {
+        "I" => return result(format!("{:#05x}", system.address_register())),
+        "PC" => return result(format!("{:#05x}", system.program_counter())),
+        "DT" => return result(system.delay_timer().to_string()),
+        "ST" => return result(system.sound_timer().to_string()),
+        "SCREENSHOT" => {
+            let paths = system.screenshot()?;
+            let paths: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+            return result(format!("saved {}", paths.join(", ")));
+        }
+        _ => {}
+    }
+
+    let register = parse_register(expression)
+        .or_else(|| (0..0x10).find(|register| system.debug_map().alias(*register) == Some(expression)));
+    if let Some(register) = register {
+        let value = system.registers()[register as usize];
+        return result(format!("{:#04x} ({})", value, value));
+    }
+
+    Err(format!("cannot evaluate `{}`", expression))
+}
+
+fn read_memory(system: &System, arguments: &Value) -> Result<Value, String> {
+    let address = arguments["memoryReference"].as_str().and_then(parse_address).ok_or("invalid memory reference")?;
+    let offset = arguments["offset"].as_i64().unwrap_or(0);
+    let count = arguments["count"].as_u64().unwrap_or(0) as usize;
+
+    let memory = system.memory();
+    let start = (address as i64).saturating_add(offset).max(0) as usize;
+    let start = start.min(memory.len());
+    let end = start.saturating_add(count).min(memory.len());
+
+    Ok(json!({
+        "address": format!("{:#05x}", start),
+        "unreadableBytes": count - (end - start),
+        "data": base64(&memory[start..end]),
+    }))
+}
+
+fn parse_address(reference: &str) -> Option<Address> {
+    parse_number(reference).map(|address| address as Address)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor, process};
+
+    use super::*;
+
+    const PROGRAM: &str = "\
+start:
+    LD V0, 5
+    CALL add_one
+    CALL add_one
+loop:
+    JP loop
+add_one:
+    ADD V0, 1
+    LD V1, V0
+    RET
+";
+
+    /// Drives a server the way an editor would, checking every message it sends back is numbered
+    /// one after the last.
+    struct Client {
+        server: Server<Vec<u8>>,
+        sequence: u64,
+        received: usize,
+        last_seen: u64,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            let mut server = Server::new(vec![]);
+            server.create_system = System::headless;
+
+            Client { server, sequence: 0, received: 0, last_seen: 0 }
+        }
+
+        /// Sends a framed request, returning the response followed by any events it caused.
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.sequence += 1;
+            let request = json!({ "seq": self.sequence, "type": "request", "command": command, "arguments": arguments }).to_string();
+            let framed = format!("Content-Length: {}\r\n\r\n{}", request.len(), request);
+
+            let message = read_message(&mut Cursor::new(framed)).unwrap().unwrap();
+            self.server.handle(&message).unwrap();
+
+            let messages = self.messages();
+            assert_eq!(messages[0]["type"], "response");
+            assert_eq!(messages[0]["request_seq"], self.sequence);
+            assert_eq!(messages[0]["command"], command);
+            messages
+        }
+
+        /// Sends a request which must succeed, returning the body of its response.
+        fn succeed(&mut self, command: &str, arguments: Value) -> Value {
+            let messages = self.request(command, arguments);
+            assert_eq!(messages[0]["success"], true, "{} failed: {}", command, messages[0]["message"]);
+            messages[0]["body"].clone()
+        }
+
+        /// Runs the program until the server reports it stopped, returning why.
+        fn stopped(&mut self) -> String {
+            for _ in 0..100 {
+                self.server.run_system().unwrap();
+
+                let stopped = self.messages().into_iter().find(|message| message["event"] == "stopped");
+                if let Some(stopped) = stopped {
+                    assert_eq!(stopped["body"]["threadId"], THREAD_ID);
+                    return stopped["body"]["reason"].as_str().unwrap().to_string();
+                }
+            }
+
+            panic!("the program never stopped");
+        }
+
+        fn messages(&mut self) -> Vec<Value> {
+            let output = &self.server.output[self.received..];
+            self.received = self.server.output.len();
+
+            let mut reader = Cursor::new(output);
+            let mut messages = vec![];
+            while let Some(message) = read_message(&mut reader).unwrap() {
+                self.last_seen += 1;
+                assert_eq!(message["seq"], self.last_seen);
+                messages.push(message);
+            }
+
+            messages
+        }
+
+        fn register(&mut self, name: &str) -> String {
+            let body = self.succeed("variables", json!({ "variablesReference": REGISTERS_REFERENCE }));
+            let variable = body["variables"].as_array().unwrap().iter()
+                .find(|variable| variable["name"] == name)
+                .cloned()
+                .unwrap();
+
+            variable["value"].as_str().unwrap().to_string()
+        }
+    }
+
+    #[test]
+    fn a_session_stops_at_breakpoints_steps_and_reads_memory() {
+        let path = env::temp_dir().join(format!("alvin-dap-{}.asm", process::id()));
+        fs::write(&path, PROGRAM).unwrap();
+        let source = path.to_string_lossy().to_string();
+
+        let mut client = Client::new();
+
+        let capabilities = client.succeed("initialize", json!({ "adapterID": "alvin" }));
+        assert_eq!(capabilities["supportsReadMemoryRequest"], true);
+
+        let messages = client.request("launch", json!({ "program": source }));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(messages[0]["success"], true, "{}", messages[0]["message"]);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        let body = client.succeed("setBreakpoints", json!({ "source": { "path": source }, "breakpoints": [{ "line": 3 }] }));
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        assert_eq!(body["breakpoints"][0]["instructionReference"], "0x202");
+
+        client.succeed("configurationDone", json!({}));
+        assert_eq!(client.stopped(), "breakpoint");
+
+        let body = client.succeed("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(body["totalFrames"], 1);
+        assert_eq!(body["stackFrames"][0]["line"], 3);
+        assert_eq!(body["stackFrames"][0]["name"], "start+2");
+        assert_eq!(client.register("V0"), "0x05 (5)");
+
+        // Stepping over the call runs the whole function
+        client.succeed("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "step");
+        assert_eq!(client.register("PC"), "0x204");
+        assert_eq!(client.register("V1"), "0x06 (6)");
+
+        client.succeed("setBreakpoints", json!({ "source": { "path": source }, "breakpoints": [{ "line": 9 }] }));
+        client.succeed("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "breakpoint");
+
+        let body = client.succeed("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(body["totalFrames"], 2);
+        assert_eq!(body["stackFrames"][0]["name"], "add_one+2");
+
+        client.succeed("stepOut", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "step");
+        assert_eq!(client.register("PC"), "0x206");
+        assert_eq!(client.register("V1"), "0x07 (7)");
+
+        let body = client.succeed("readMemory", json!({ "memoryReference": "0x200", "count": 6 }));
+        assert_eq!(body["address"], "0x200");
+        assert_eq!(body["unreadableBytes"], 0);
+        assert_eq!(body["data"], base64(&[0x60, 0x05, 0x22, 0x08, 0x22, 0x08]));
+
+        let messages = client.request("disconnect", json!({}));
+        assert_eq!(messages[0]["success"], true);
+        assert_eq!(messages[1]["event"], "terminated");
+        assert!(client.server.finished);
+    }
+
+    #[test]
+    fn requests_needing_a_program_fail_until_one_is_launched() {
+        let mut client = Client::new();
+        client.succeed("initialize", json!({}));
+
+        let messages = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(messages[0]["success"], false);
+
+        let messages = client.request("launch", json!({ "program": "/nonexistent/alvin.ch8" }));
+        assert_eq!(messages[0]["success"], false);
+        assert_eq!(messages.len(), 1, "a failed launch must not initialize the session");
+
+        let messages = client.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(messages[0]["success"], false);
+
+        let messages = client.request("unknownRequest", json!({}));
+        assert_eq!(messages[0]["success"], false);
+    }
+
+    #[test]
+    fn reads_past_the_end_of_memory_are_clamped() {
+        let platform = Platform::Chip8;
+        let system = System::headless(&[], Layout::for_platform(platform), Font::for_platform(platform));
+
+        let body = read_memory(&system, &json!({ "memoryReference": "0xFFE", "count": u64::MAX })).unwrap();
+        assert_eq!(body["address"], "0xffe");
+        assert_eq!(body["data"], base64(&system.memory()[0xFFE..]));
+
+        let body = read_memory(&system, &json!({ "memoryReference": "0x200", "offset": i64::MAX, "count": 4 })).unwrap();
+        assert_eq!(body["unreadableBytes"], 4);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::{
+    assembler::parse_number,
+    emulator::{Address, Register},
+};
+
+const HEADER: &str = "# alvin debug map";
+
+#[derive(Clone)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: usize,
+}
+
+/// Maps ROM addresses back to the source they were built from.
+///
+/// Written next to assembled ROMs as a plain text file with one record per line:
+/// `line <address> <line> <file>`, `label <address> <name>` and `alias <register> <name>`.
+#[derive(Default)]
+pub struct DebugMap {
+    lines: BTreeMap<Address, SourceLine>,
+    symbols: BTreeMap<Address, String>,
+    aliases: BTreeMap<Register, String>,
+    sources: RefCell<HashMap<String, Option<Vec<String>>>>,
+}
+
+impl DebugMap {
+    pub fn new() -> DebugMap {
+        DebugMap::default()
+    }
+
+    pub fn load(path: &Path) -> io::Result<DebugMap> {
+        let contents = fs::read_to_string(path)?;
+        let mut debug_map = DebugMap::new();
+
+        for (number, record) in contents.lines().enumerate() {
+            let record = record.trim();
+            if record.is_empty() || record.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                let message = format!("{}:{}: invalid debug map record", path.display(), number + 1);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            };
+
+            let mut fields = record.splitn(4, ' ');
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some("line"), Some(address), Some(line), Some(file)) => {
+                    let address = parse_number(address).ok_or_else(invalid)?;
+                    let line = line.parse().map_err(|_| invalid())?;
+                    debug_map.add_line(address as Address, file, line);
+                }
+                (Some("label"), Some(address), Some(name), None) => {
+                    let address = parse_number(address).ok_or_else(invalid)?;
+                    debug_map.add_symbol(address as Address, name);
+                }
+                (Some("alias"), Some(register), Some(name), None) => {
+                    let register = parse_number(register).ok_or_else(invalid)?;
+                    debug_map.add_alias(register as Register, name);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(debug_map)
+    }
+
+    /// Adds the labels from a symbol file, for naming addresses in ROMs built elsewhere.
+    /// Names given here take precedence over the ones recorded by the assembler.
+    ///
+    /// Accepts `name = 0x2a0` lines, Octo style `:const name 0x2a0` lines, or the JSON object of
+    /// label addresses Octo exports, e.g. `{"draw_player": 672}`.
+    pub fn load_symbols(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+
+        if contents.trim_start().starts_with('{') {
+            let labels: HashMap<String, u64> = serde_json::from_str(&contents)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+            let mut labels: Vec<_> = labels.into_iter().collect();
+            labels.sort();
+            for (name, address) in labels {
+                self.symbols.insert(address as Address, name);
+            }
+
+            return Ok(());
+        }
+
+        for (number, record) in contents.lines().enumerate() {
+            let record = record.split(&['#', ';'][..]).next().unwrap_or("").trim();
+            if record.is_empty() {
+                continue;
+            }
+
+            let invalid = || {
+                let message = format!("{}:{}: invalid symbol definition", path.display(), number + 1);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            };
+
+            let fields: Vec<&str> = record.split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|field| !field.is_empty())
+                .collect();
+
+            let (name, address) = match fields.as_slice() {
+                [":const", name, address] | [name, address] => (name, address),
+                _ => return Err(invalid()),
+            };
+
+            let address = parse_number(address).ok_or_else(invalid)?;
+            self.symbols.insert(address as Address, name.to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut contents = format!("{}\n", HEADER);
+
+        for (address, name) in self.symbols.iter() {
+            contents.push_str(&format!("label {:#05x} {}\n", address, name));
+        }
+
+        for (register, name) in self.aliases.iter() {
+            contents.push_str(&format!("alias {:#x} {}\n", register, name));
+        }
+
+        for (address, source) in self.lines.iter() {
+            contents.push_str(&format!("line {:#05x} {} {}\n", address, source.line, source.file));
+        }
+
+        fs::write(path, contents)
+    }
+
+    pub fn add_line(&mut self, address: Address, file: &str, line: usize) {
+        self.lines.insert(address, SourceLine { file: file.to_string(), line });
+    }
+
+    pub fn add_symbol(&mut self, address: Address, name: &str) {
+        self.symbols.entry(address).or_insert_with(|| name.to_string());
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (Address, &str)> {
+        self.symbols.iter().map(|(address, name)| (*address, name.as_str()))
+    }
+
+    pub fn add_alias(&mut self, register: Register, name: &str) {
+        self.aliases.insert(register, name.to_string());
+    }
+
+    pub fn source_line(&self, address: Address) -> Option<&SourceLine> {
+        self.lines.get(&address)
+    }
+
+    /// Finds the first address built from `line` of `file`, or from the closest line after it.
+    pub fn resolve_line(&self, file: &Path, line: usize) -> Option<(Address, usize)> {
+        let wanted = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+
+        self.lines.iter()
+            .filter(|(_, source)| source.line >= line)
+            .filter(|(_, source)| {
+                let path = Path::new(&source.file);
+                path.canonicalize().map(|path| path == wanted).unwrap_or_else(|_| path.file_name() == file.file_name())
+            })
+            .min_by_key(|(address, source)| (source.line, **address))
+            .map(|(address, source)| (*address, source.line))
+    }
+
+    /// The text of the source line `address` was built from, if the source file can be read.
+    pub fn source_text(&self, address: Address) -> Option<String> {
+        let source = self.source_line(address)?;
+
+        let mut sources = self.sources.borrow_mut();
+        let lines = sources.entry(source.file.clone()).or_insert_with(|| {
+            fs::read_to_string(&source.file).ok().map(|text| text.lines().map(str::to_string).collect())
+        });
+
+        lines.as_ref()
+            .and_then(|lines| lines.get(source.line.checked_sub(1)?))
+            .map(|text| text.trim().to_string())
+    }
+
+    /// Names `address` relative to the closest symbol at or before it, e.g. `draw_player+4`.
+    pub fn symbolize(&self, address: Address) -> Option<String> {
+        let (symbol_address, name) = self.symbols.range(..=address).next_back()?;
+
+        match address - symbol_address {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+{}", name, offset)),
+        }
+    }
+
+    pub fn alias(&self, register: Register) -> Option<&str> {
+        self.aliases.get(&register).map(String::as_str)
+    }
+}
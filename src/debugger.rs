@@ -0,0 +1,120 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    emulator::{Address, System},
+    opcode::Opcode,
+};
+
+const WORD_SIZE: Address = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Entry,
+    Breakpoint,
+    Step,
+    Pause,
+    Exception,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Continue,
+    StepIn,
+    StepOver { return_to: Address, depth: usize },
+    StepOut { depth: usize },
+}
+
+/// Drives a `System` one instruction at a time, stopping on breakpoints and finished steps.
+pub struct Debugger {
+    source_breakpoints: HashMap<String, Vec<Address>>,
+    instruction_breakpoints: BTreeSet<Address>,
+    mode: Option<Mode>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            mode: None,
+        }
+    }
+
+    /// Replaces the breakpoints set from `source`, clients always send the full set per file.
+    pub fn set_source_breakpoints(&mut self, source: &str, addresses: Vec<Address>) {
+        self.source_breakpoints.insert(source.to_string(), addresses);
+    }
+
+    pub fn set_instruction_breakpoints(&mut self, addresses: Vec<Address>) {
+        self.instruction_breakpoints = addresses.into_iter().collect();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = Some(Mode::Continue);
+    }
+
+    pub fn step_in(&mut self) {
+        self.mode = Some(Mode::StepIn);
+    }
+
+    pub fn step_over(&mut self, system: &System) {
+        self.mode = match system.current_opcode() {
+            Some(Opcode::CallFunction(_)) => Some(Mode::StepOver {
+                return_to: system.program_counter() + WORD_SIZE,
+                depth: system.call_stack().len(),
+            }),
+            _ => Some(Mode::StepIn),
+        };
+    }
+
+    pub fn step_out(&mut self, system: &System) {
+        self.mode = Some(Mode::StepOut { depth: system.call_stack().len() });
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = None;
+    }
+
+    /// Executes one instruction if the program is running and reports why it stopped, if it did.
+    pub fn advance(&mut self, system: &mut System) -> Option<StopReason> {
+        let mode = self.mode?;
+
+        if system.step().is_err() {
+            self.mode = None;
+            return Some(StopReason::Exception);
+        }
+
+        let program_counter = system.program_counter();
+        let depth = system.call_stack().len();
+
+        let finished_step = match mode {
+            Mode::Continue => false,
+            Mode::StepIn => true,
+            Mode::StepOver { return_to, depth: call_depth } => program_counter == return_to && depth == call_depth,
+            Mode::StepOut { depth: call_depth } => depth < call_depth,
+        };
+
+        let reason = if self.has_breakpoint(program_counter) {
+            Some(StopReason::Breakpoint)
+        } else if finished_step {
+            Some(StopReason::Step)
+        } else {
+            None
+        };
+
+        if reason.is_some() {
+            self.mode = None;
+        }
+
+        reason
+    }
+
+    fn has_breakpoint(&self, address: Address) -> bool {
+        self.instruction_breakpoints.contains(&address)
+            || self.source_breakpoints.values().any(|addresses| addresses.contains(&address))
+    }
+}
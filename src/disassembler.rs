@@ -1,17 +1,344 @@
-use crate::opcode::Opcode;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
 
-pub fn disassemble(buffer: &[u8]) {
-    println!("HEX\tOP\tARG1\tARG2\tARG3");
-    println!("---\t--\t----\t----\t----");
-    for chunk in buffer.chunks(2) {
-        let first_byte = chunk[0];
-        let second_byte = chunk[1];
+use crate::{
+    debug_map::DebugMap,
+    emulator::Address,
+    memory::PROGRAM_START,
+    opcode::{hex_address, Opcode, Syntax},
+};
 
-        print!("{:#02x}{:02x}\t", first_byte, second_byte);
-        if let Ok(opcode) = Opcode::from(first_byte, second_byte) {
-            print!("{:?}", opcode);
+const WORD_SIZE: Address = 2;
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    Fallthrough,
+    Skip,
+    Jump,
+    Call,
+    Computed,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum LabelKind {
+    Data,
+    Jump,
+    Subroutine,
+    Entry,
+}
+
+/// The result of walking a program's control flow from its entry point.
+///
+/// Only bytes reachable through jumps, calls, skips and fallthrough are
+/// decoded as instructions, everything else is treated as data.
+pub struct Analysis<'a> {
+    program: &'a [u8],
+    origin: Address,
+    instructions: BTreeMap<Address, Opcode>,
+    labels: BTreeMap<Address, LabelKind>,
+    names: BTreeMap<Address, String>,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn new(program: &'a [u8], origin: Address) -> Analysis<'a> {
+        let mut analysis = Analysis {
+            program,
+            origin,
+            instructions: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            names: BTreeMap::new(),
+        };
+
+        analysis.add_label(origin, LabelKind::Entry);
+
+        let mut pending = vec![origin];
+        while let Some(address) = pending.pop() {
+            if analysis.instructions.contains_key(&address) || analysis.overlaps_instruction(address) {
+                continue;
+            }
+
+            let opcode = match analysis.decode(address) {
+                Some(opcode) => opcode,
+                None => continue,
+            };
+
+            analysis.instructions.insert(address, opcode);
+
+            if let Opcode::SetAddressReg(target) = opcode {
+                analysis.add_label(target, LabelKind::Data);
+            }
+
+            for (kind, target) in successors(address, opcode).into_iter().rev() {
+                match kind {
+                    EdgeKind::Jump => analysis.add_label(target, LabelKind::Jump),
+                    EdgeKind::Call => analysis.add_label(target, LabelKind::Subroutine),
+                    EdgeKind::Computed => {
+                        // The real destination depends on V0, so all we can do is mark the table
+                        analysis.add_label(target, LabelKind::Jump);
+                        continue;
+                    }
+                    EdgeKind::Fallthrough | EdgeKind::Skip => {}
+                }
+
+                pending.push(target);
+            }
+        }
+
+        analysis
+    }
+
+    /// Uses the names from `symbols` in place of the generated ones, labelling any new addresses they mention.
+    pub fn name_labels(&mut self, symbols: &DebugMap) {
+        for (address, name) in symbols.symbols() {
+            // Octo sources need the entry point to stay `main`
+            if address == self.origin {
+                continue;
+            }
+
+            self.add_label(address, LabelKind::Data);
+            self.names.insert(address, name.to_string());
+        }
+    }
+
+    pub fn origin(&self) -> Address {
+        self.origin
+    }
+
+    /// One past the last address, which is past the end of memory for programs that fill it.
+    pub fn end(&self) -> usize {
+        self.origin as usize + self.program.len()
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = (Address, Opcode)> + '_ {
+        self.instructions.iter().map(|(address, opcode)| (*address, *opcode))
+    }
+
+    pub fn instruction(&self, address: Address) -> Option<Opcode> {
+        self.instructions.get(&address).cloned()
+    }
+
+    pub fn label(&self, address: Address) -> Option<String> {
+        if let Some(name) = self.names.get(&address) {
+            return Some(name.clone());
+        }
+
+        self.labels.get(&address).map(|kind| label_name(address, *kind))
+    }
+
+    pub fn byte(&self, address: Address) -> u8 {
+        self.program[(address - self.origin) as usize]
+    }
+
+    /// Labels which point into the middle of a decoded instruction or outside of the program.
+    pub fn detached_labels(&self) -> BTreeSet<Address> {
+        self.labels.keys()
+            .filter(|&&address| !self.contains(address) || self.overlaps_instruction(address))
+            .cloned()
+            .collect()
+    }
+
+    fn contains(&self, address: Address) -> bool {
+        address >= self.origin && (address as usize) < self.end()
+    }
+
+    fn overlaps_instruction(&self, address: Address) -> bool {
+        address > self.origin && self.instructions.contains_key(&(address - 1))
+    }
+
+    fn decode(&self, address: Address) -> Option<Opcode> {
+        let second = address.checked_add(1)?;
+        if !self.contains(address) || !self.contains(second) || self.instructions.contains_key(&second) {
+            return None;
+        }
+
+        Opcode::from(self.byte(address), self.byte(second)).ok()
+    }
+
+    fn add_label(&mut self, address: Address, kind: LabelKind) {
+        let existing = self.labels.entry(address).or_insert(kind);
+        if kind > *existing {
+            *existing = kind;
+        }
+    }
+}
+
+/// Where control can go after executing `opcode` at `address`, leaving aside returns.
+///
+/// A call's fallthrough is where the subroutine returns to.
+pub fn successors(address: Address, opcode: Opcode) -> Vec<(EdgeKind, Address)> {
+    // Nothing follows an instruction at the very end of memory
+    let next = address.checked_add(WORD_SIZE);
+    let skipped = next.and_then(|next| next.checked_add(WORD_SIZE));
+
+    let edges = match opcode {
+        Opcode::Goto(target) => vec![(EdgeKind::Jump, Some(target))],
+        Opcode::CallFunction(target) => vec![(EdgeKind::Call, Some(target)), (EdgeKind::Fallthrough, next)],
+        Opcode::JumpOffset(target) => vec![(EdgeKind::Computed, Some(target))],
+        Opcode::Return => vec![],
+        Opcode::SkipEq(..) |
+        Opcode::SkipNEq(..) |
+        Opcode::SkipEqReg(..) |
+        Opcode::SkipNEqReg(..) |
+        Opcode::SkipKeyPress(..) |
+        Opcode::SkipNoKeyPress(..) => vec![(EdgeKind::Fallthrough, next), (EdgeKind::Skip, skipped)],
+        _ => vec![(EdgeKind::Fallthrough, next)],
+    };
+
+    edges.into_iter()
+        .filter_map(|(kind, target)| target.map(|target| (kind, target)))
+        .collect()
+}
+
+fn label_name(address: Address, kind: LabelKind) -> String {
+    match kind {
+        LabelKind::Entry => "main".to_string(),
+        LabelKind::Subroutine => format!("sub_{:03x}", address),
+        LabelKind::Jump => format!("label_{:03x}", address),
+        LabelKind::Data => format!("data_{:03x}", address),
+    }
+}
+
+pub fn disassemble(buffer: &[u8], syntax: Syntax, symbols: &DebugMap) {
+    print!("{}", disassembly(buffer, syntax, symbols));
+}
+
+/// The source `disassemble` prints, which assembles back into `buffer`.
+pub fn disassembly(buffer: &[u8], syntax: Syntax, symbols: &DebugMap) -> String {
+    let mut out = String::new();
+    let mut analysis = Analysis::new(buffer, PROGRAM_START);
+    analysis.name_labels(symbols);
+
+    let name = |address: Address| analysis.label(address).unwrap_or_else(|| hex_address(address));
+
+    for address in analysis.detached_labels() {
+        let label = analysis.label(address).unwrap();
+        match syntax {
+            Syntax::Classic => writeln!(out, "{} EQU {:#05x}", label, address).unwrap(),
+            Syntax::Octo => writeln!(out, ":const {} {:#05x}", label, address).unwrap(),
+        }
+    }
+
+    // Counted from the origin, as the address after a program filling memory doesn't fit in an `Address`
+    let address_at = |offset: usize| analysis.origin() + offset as Address;
+    let mut offset = 0;
+    while offset < buffer.len() {
+        let address = address_at(offset);
+        if let Some(label) = analysis.label(address) {
+            match syntax {
+                Syntax::Classic => writeln!(out, "{}:", label).unwrap(),
+                Syntax::Octo => writeln!(out, ": {}", label).unwrap(),
+            }
+        }
+
+        if let Some(opcode) = analysis.instruction(address) {
+            let comment = format!("{:#05x}  {:02x}{:02x}", address, analysis.byte(address), analysis.byte(address + 1));
+            write_line(&mut out, syntax, &opcode.render(syntax, &name), &comment);
+
+            offset += WORD_SIZE as usize;
+            continue;
+        }
+
+        let mut bytes = vec![];
+        loop {
+            bytes.push(format!("{:#04x}", buffer[offset]));
+            offset += 1;
+
+            let boundary = offset >= buffer.len()
+                || bytes.len() == DATA_BYTES_PER_LINE
+                || analysis.instruction(address_at(offset)).is_some()
+                || analysis.label(address_at(offset)).is_some();
+            if boundary {
+                break;
+            }
+        }
+
+        let data = match syntax {
+            Syntax::Classic => format!("db {}", bytes.join(", ")),
+            Syntax::Octo => bytes.join(" "),
+        };
+        write_line(&mut out, syntax, &data, &format!("{:#05x}", address));
+    }
+
+    out
+}
+
+fn write_line(out: &mut String, syntax: Syntax, source: &str, comment: &str) {
+    match syntax {
+        Syntax::Classic => writeln!(out, "\t{:<28}; {}", source, comment).unwrap(),
+        Syntax::Octo => writeln!(out, "  {:<28}# {}", source, comment).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, rc::Rc};
+
+    use super::*;
+    use crate::{assembler::assemble_file, octo::compile};
+
+    /// The most a ROM can hold below the end of memory.
+    const CHUNK_SIZE: usize = 0xE00;
+
+    /// Every instruction the decoder knows, each behind a skip so all of them are reachable, split
+    /// into ROMs small enough to assemble.
+    fn every_instruction() -> Vec<Vec<u8>> {
+        let pairs: Vec<u8> = (0..=0xFFFFu16)
+            .filter(|word| Opcode::from((word >> 8) as u8, *word as u8).is_ok())
+            .flat_map(|word| vec![0x30, 0x00, (word >> 8) as u8, word as u8])
+            .collect();
+
+        pairs.chunks(CHUNK_SIZE).map(|chunk| chunk.to_vec()).collect()
+    }
+
+    /// A jump over every byte value, including words which don't decode such as `F401`.
+    fn data() -> Vec<u8> {
+        let mut rom = vec![0x12, 0x00];
+        rom.extend(0..=0xFFu8);
+        rom.extend_from_slice(&[0xF4, 0x01, 0xA2, 0x02, 0xD0, 0x15]);
+        rom
+    }
+
+    #[test]
+    fn classic_disassembly_assembles_back_into_the_rom() {
+        for (index, rom) in every_instruction().into_iter().chain(Some(data())).enumerate() {
+            let source = disassembly(&rom, Syntax::Classic, &DebugMap::new());
+            let path = env::temp_dir().join(format!("alvin-round-trip-{}-{}.asm", process::id(), index));
+            fs::write(&path, &source).unwrap();
+
+            let program = assemble_file(&path);
+            fs::remove_file(&path).unwrap();
+
+            let program = program.unwrap_or_else(|errors| panic!("{}\n{}", source, errors[0]));
+            assert!(program.rom == rom, "ROM {} changed after assembling its disassembly", index);
         }
+    }
+
+    #[test]
+    fn octo_disassembly_compiles_back_into_the_rom() {
+        for (index, rom) in every_instruction().into_iter().chain(Some(data())).enumerate() {
+            let source = disassembly(&rom, Syntax::Octo, &DebugMap::new());
+
+            let program = compile(&source, Rc::from("round-trip.8o"))
+                .unwrap_or_else(|errors| panic!("{}\n{}", source, errors[0]));
+            assert!(program.rom == rom, "ROM {} changed after compiling its disassembly", index);
+        }
+    }
+
+    #[test]
+    fn programs_can_run_up_to_the_end_of_memory() {
+        // `LD V0, 0` all the way up to 0xFFFF, the most an XO-CHIP program can hold
+        let rom: Vec<u8> = [0x60, 0x00].iter().cycle().take(0x10000 - PROGRAM_START as usize).cloned().collect();
+
+        let analysis = Analysis::new(&rom, PROGRAM_START);
+        assert_eq!(analysis.end(), 0x10000);
+        assert!(analysis.instruction(0xFFFE) == Some(Opcode::Set(0, 0)));
+
+        let source = disassembly(&rom, Syntax::Octo, &DebugMap::new());
+        assert!(source.trim_end().ends_with("# 0xfffe  6000"));
 
-        println!();
+        assert!(crate::analyzer::analyze(&rom).unknown.is_empty());
     }
-}
\ No newline at end of file
+}
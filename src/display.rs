@@ -1,86 +1,308 @@
+use std::mem;
+
 use sdl2::{
     self,
-    pixels::Color,
-    rect::Point,
-    render::Canvas,
-    video::Window,
+    pixels::{Color, PixelFormatEnum},
+    rect::{Point, Rect},
+    render::{Canvas, Texture},
+    video::{FullscreenType, Window, WindowPos},
+};
+
+use crate::{
+    framebuffer::Framebuffer,
+    osd::{layout_text, Osd},
+    palette::Palette,
+    persistence::{Persistence, PersistenceFilter},
+    upscale::Upscaler,
 };
 
-const BG_COLOR: Color = Color { r: 53, g: 59, b: 115, a: 0xFF };
-const FG_COLOR: Color = Color { r: 255, g: 255, b: 41, a: 0xFF };
+pub const DEFAULT_SCALE: u32 = 10;
+/// A 4096x2048 window, wider than any screen a window can be opened on.
+pub const MAX_SCALE: u32 = 64;
+const LETTERBOX_COLOR: Color = Color { r: 0, g: 0, b: 0, a: 0xFF };
+/// On-screen text is drawn finer than the CHIP-8 pixels, so messages fit across the screen.
+const OSD_SCALE: f32 = 2.0;
+const OSD_MARGIN: i32 = 2;
+const BYTES_PER_PIXEL: usize = 3;
 
 pub struct Display {
-    working_screen: [[bool; 32]; 64],
+    /// The window and what's drawn into it, missing when running without one.
+    window: Option<WindowCanvas>,
+    texture_size: (usize, usize),
+    palette: Palette,
+    osd: Osd,
+    /// Whether drawing on-screen text has failed and been reported.
+    osd_failed: bool,
+    /// The framebuffer version on screen, if what's on screen is still up to date.
+    presented: Option<u64>,
+    /// The window size the screen was last laid out for.
+    output_size: (u32, u32),
+    persistence: PersistenceFilter,
+    /// Whether the persistence filter is still fading pixels, so frames need presenting even
+    /// when the framebuffer hasn't changed.
+    settling: bool,
+    upscaler: Upscaler,
+    image: Vec<Color>,
+}
+
+struct WindowCanvas {
     canvas: Canvas<Window>,
+    /// Belongs to the canvas rather than borrowing a texture creator, using sdl2's
+    /// `unsafe_textures`, so it has to be destroyed by hand when replaced.
+    texture: Texture,
 }
 
 impl Display {
     pub fn new(sdl_context: &sdl2::Sdl) -> Display {
         let video_subsystem = sdl_context.video().unwrap();
 
-        let window = video_subsystem.window("Alvin", 640, 320)
-            .position_centered().build().unwrap();
+        let (width, height) = Framebuffer::new().dimensions();
+        let window = video_subsystem.window("Alvin", width as u32 * DEFAULT_SCALE, height as u32 * DEFAULT_SCALE)
+            .position_centered().resizable().build().unwrap();
 
         let mut canvas = window.into_canvas().accelerated().build().unwrap();
-        canvas.set_scale(10.0, 10.0);
-        canvas.set_draw_color(BG_COLOR);
+        canvas.set_draw_color(Palette::default().background());
         canvas.clear();
 
+        let texture_size = Framebuffer::new().dimensions();
+        let texture = create_texture(&canvas, texture_size);
+
         Display {
-            working_screen: [[false; 32]; 64],
-            canvas,
+            window: Some(WindowCanvas { canvas, texture }),
+            texture_size,
+            palette: Palette::default(),
+            osd: Osd::default(),
+            osd_failed: false,
+            presented: None,
+            output_size: (0, 0),
+            persistence: PersistenceFilter::new(Persistence::Off),
+            settling: false,
+            upscaler: Upscaler::Nearest,
+            image: vec![],
         }
     }
 
-    pub fn screen_dimensions(&self) -> (usize, usize) {
-        (self.working_screen.len(), self.working_screen[0].len())
+    /// A display without a window, for running the emulator in tests.
+    #[cfg(test)]
+    pub fn headless() -> Display {
+        Display {
+            window: None,
+            texture_size: Framebuffer::new().dimensions(),
+            palette: Palette::default(),
+            osd: Osd::default(),
+            osd_failed: false,
+            presented: None,
+            output_size: (0, 0),
+            persistence: PersistenceFilter::new(Persistence::Off),
+            settling: false,
+            upscaler: Upscaler::Nearest,
+            image: vec![],
+        }
     }
 
-    pub fn clear(&mut self) {
-        self.canvas.set_draw_color(BG_COLOR);
-        self.working_screen = [[false; 32]; 64];
-        self.canvas.clear();
-        self.canvas.present();
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = PersistenceFilter::new(persistence);
+        self.presented = None;
+    }
+
+    pub fn set_upscaler(&mut self, upscaler: Upscaler) {
+        self.upscaler = upscaler;
+        self.presented = None;
+    }
+
+    /// Resizes the window to show a low resolution screen at `scale` times its size.
+    pub fn set_scale(&mut self, scale: u32) {
+        let (width, height) = Framebuffer::new().dimensions();
+        let window = match self.window.as_mut() {
+            Some(window) => window.canvas.window_mut(),
+            None => return,
+        };
+
+        let size = (width as u32).checked_mul(scale).zip((height as u32).checked_mul(scale));
+        let result = match size {
+            Some((width, height)) => window.set_size(width, height).map_err(|error| error.to_string()),
+            None => Err(format!("{} times larger than {}x{} is too large", scale, width, height)),
+        };
+
+        if let Err(error) = result {
+            println!("WARNING: could not resize the window: {}", error);
+        }
+        window.set_position(WindowPos::Centered, WindowPos::Centered);
     }
 
-    pub fn draw(&mut self, x: u8, y: u8, mut sprite: u8) -> bool {
-        let mut collision = false;
+    /// Switches between a window and borderless fullscreen at the desktop's resolution.
+    pub fn toggle_fullscreen(&mut self) {
+        let window = match self.window.as_mut() {
+            Some(window) => window.canvas.window_mut(),
+            None => return,
+        };
+
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+
+        if let Err(error) = window.set_fullscreen(fullscreen) {
+            println!("WARNING: could not toggle fullscreen: {}", error);
+        }
+        self.presented = None;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.presented = None;
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    /// Shows `message` over the screen for a couple of seconds.
+    pub fn show_message(&mut self, message: String) {
+        self.osd.show_message(message);
+        self.presented = None;
+    }
+
+    /// Shows `status` over the screen until it's replaced or cleared.
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.osd.set_status(status);
+        self.presented = None;
+    }
+
+    /// Puts the framebuffer on screen, to be called once a frame. Frames where neither the
+    /// framebuffer nor the text over it changed are skipped.
+    pub fn present(&mut self, framebuffer: &Framebuffer) {
+        if self.osd.expire() {
+            self.presented = None;
+        }
+
+        let window = match self.window.as_mut() {
+            Some(window) => window,
+            None => return,
+        };
+
+        let output_size = window.canvas.output_size().unwrap();
+        if output_size != self.output_size {
+            self.output_size = output_size;
+            self.presented = None;
+        }
+
+        if self.presented == Some(framebuffer.version()) && !self.settling {
+            return;
+        }
+
+        self.settling = self.persistence.apply(framebuffer, &self.palette, &mut self.image);
 
-        let x = x as usize;
-        let mut y = y as usize;
+        let (width, height) = framebuffer.dimensions();
+        let factor = self.upscaler.factor();
+        let image = self.upscaler.apply(&self.image, width, height);
 
-        for _ in 0x0..0x8 {
-            let highest_bit = (sprite & 0x80) == 0x80;
-            sprite = sprite << 1;
+        if (width * factor, height * factor) != self.texture_size {
+            self.texture_size = (width * factor, height * factor);
+            let texture = mem::replace(&mut window.texture, create_texture(&window.canvas, self.texture_size));
 
-            collision |= self.working_screen[y][x];
+            // Sound, as the canvas the old texture belongs to is still alive
+            unsafe { texture.destroy() };
+        }
 
-            self.working_screen[y][x] ^= highest_bit;
+        let (width, _) = self.texture_size;
+        window.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (i, color) in image.iter().enumerate() {
+                let offset = (i / width) * pitch + (i % width) * BYTES_PER_PIXEL;
 
-            if y == self.working_screen.len() - 1 {
-                y = 0;
-            } else {
-                y += 1;
+                buffer[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&[color.r, color.g, color.b]);
+            }
+        }).unwrap();
+
+        let screen = screen_rect(self.output_size, self.texture_size);
+        window.canvas.set_draw_color(LETTERBOX_COLOR);
+        window.canvas.clear();
+        window.canvas.copy(&window.texture, None, screen).unwrap();
+        if let Err(error) = window.render_osd(&self.osd, &self.palette, screen) {
+            // Drawing fails the same way every frame, so only say so once
+            if !self.osd_failed {
+                println!("WARNING: could not draw on-screen text: {}", error);
+                self.osd_failed = true;
             }
         }
+        window.canvas.present();
 
-        return collision;
+        self.presented = Some(framebuffer.version());
     }
 
-    pub fn render(&mut self) {
-        for i in 0..self.working_screen.len() {
-            for j in 0..self.working_screen[i].len() {
-                let color = if self.working_screen[i][j] {
-                    FG_COLOR
-                } else {
-                    BG_COLOR
-                };
-
-                self.canvas.set_draw_color(color);
-                self.canvas.draw_point(Point::new(i as i32, j as i32));
+    /// How many window pixels across each framebuffer pixel is drawn.
+    pub fn pixel_scale(&self, framebuffer: &Framebuffer) -> usize {
+        if self.window.is_none() {
+            return 1;
+        }
+
+        screen_rect(self.output_size, self.texture_size).width() as usize / framebuffer.dimensions().0
+    }
+}
+
+impl WindowCanvas {
+    fn render_osd(&mut self, osd: &Osd, palette: &Palette, screen: Rect) -> Result<(), String> {
+        let lines = osd.lines();
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        self.canvas.set_scale(OSD_SCALE, OSD_SCALE)?;
+
+        let left = screen.x() / OSD_SCALE as i32 + OSD_MARGIN;
+        let top = screen.y() / OSD_SCALE as i32 + OSD_MARGIN;
+        let drawn = self.draw_lines(&lines, palette, left, top);
+
+        // Put the scale back even if drawing failed, or the next frame would be drawn scaled up
+        self.canvas.set_scale(1.0, 1.0)?;
+        drawn
+    }
+
+    fn draw_lines(&mut self, lines: &[&str], palette: &Palette, left: i32, mut top: i32) -> Result<(), String> {
+        for line in lines {
+            let (pixels, (width, height)) = layout_text(line);
+
+            // Boxed in the background colour so the text stays readable over the game
+            self.canvas.set_draw_color(palette.background());
+            self.canvas.fill_rect(Rect::new(
+                left - 1,
+                top - 1,
+                width as u32 + 2,
+                height as u32 + 2,
+            ))?;
+
+            self.canvas.set_draw_color(palette.foreground());
+            for (x, y) in pixels {
+                self.canvas.draw_point(Point::new(left + x as i32, top + y as i32))?;
             }
+
+            top += height as i32 + OSD_MARGIN;
         }
 
-        self.canvas.present();
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Where the screen goes in the window: scaled by the largest whole number that fits, so every
+/// CHIP-8 pixel is the same size, and centred with bars filling the rest.
+///
+/// Scaling by the window rather than a fixed factor keeps the window's size when SCHIP and
+/// XO-CHIP switch between 64x32 and 128x64, which share the same 2:1 shape.
+fn screen_rect((output_width, output_height): (u32, u32), texture_size: (usize, usize)) -> Rect {
+    let (width, height) = (texture_size.0 as u32, texture_size.1 as u32);
+    let scale = (output_width / width).min(output_height / height).max(1);
+
+    let (screen_width, screen_height) = (width * scale, height * scale);
+    Rect::new(
+        (output_width as i32 - screen_width as i32) / 2,
+        (output_height as i32 - screen_height as i32) / 2,
+        screen_width,
+        screen_height,
+    )
+}
+
+fn create_texture(canvas: &Canvas<Window>, (width, height): (usize, usize)) -> Texture {
+    canvas
+        .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+        .unwrap()
+}
@@ -1,19 +1,27 @@
 use std::{
     collections::VecDeque,
+    path::PathBuf,
     thread,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use rand;
 use rand::distributions::{IndependentSample, Range};
-use sdl2;
-
 use crate::{
+    debug_map::DebugMap,
     display::Display,
+    framebuffer::{Framebuffer, HIGH_RESOLUTION},
+    font::Font,
     input::{Input, InputAction},
-    memory::{load_fonts, load_program},
-    opcode::Opcode,
+    memory::{load_fonts, load_program, Layout},
+    opcode::{hex_address, Opcode, Syntax},
+    palette::Palette,
+    persistence::Persistence,
+    platform::Quirks,
+    recorder::{audio_path, Recorder, DEFAULT_RECORD_SCALE},
+    screenshot::{self, timestamp},
     sound::Sound,
+    upscale::Upscaler,
 };
 
 pub type Address = u16;
@@ -21,9 +29,15 @@ pub type Constant = u8;
 pub type Register = u8;
 
 const WORD_SIZE: u16 = 2;
+/// Bytes in each plane of a 16x16 `DXY0` sprite.
+const LARGE_SPRITE_SIZE: u16 = 32;
+/// How many pixels `00FB` and `00FC` scroll the screen sideways.
+const SCROLL_DISTANCE: isize = 4;
+const FRAME_DURATION: Duration = Duration::from_millis(16);
+const DEFAULT_SPEED: u32 = 10;
 
 pub struct System {
-    memory: [Constant; 4096],
+    memory: Vec<Constant>,
     registers: [Constant; 16],
     address_register: Address,
     stack: VecDeque<Address>,
@@ -31,24 +45,45 @@ pub struct System {
     sound_timer: Constant,
     program_counter: Address,
 
+    framebuffer: Framebuffer,
     display: Display,
     input: Input,
     sound: Sound,
 
     rng: rand::ThreadRng,
+
+    debug_map: DebugMap,
+    quirks: Quirks,
+    speed: u32,
+    layout: Layout,
+    /// What the program is called in the names of screenshots and recordings.
+    name: String,
+    recorder: Option<Recorder>,
+    record_audio: bool,
+    record_scale: usize,
 }
 
 impl System {
-    pub fn new(program: &[u8]) -> System {
-        let mut memory = [0; 4096];
-        load_fonts(&mut memory);
-        load_program(&mut memory, program);
-
+    pub fn new(program: &[u8], layout: Layout, font: Font) -> System {
         let sdl_context = sdl2::init().unwrap();
         let display = Display::new(&sdl_context);
         let input = Input::new(&sdl_context);
         let sound = Sound::new(&sdl_context);
 
+        System::with_frontend(program, layout, font, display, input, sound)
+    }
+
+    /// A system with no window, keyboard or sound, for running programs in tests.
+    #[cfg(test)]
+    pub fn headless(program: &[u8], layout: Layout, font: Font) -> System {
+        System::with_frontend(program, layout, font, Display::headless(), Input::headless(), Sound::headless())
+    }
+
+    fn with_frontend(program: &[u8], layout: Layout, font: Font, display: Display, input: Input, sound: Sound) -> System {
+        let mut memory = vec![0; layout.size];
+        load_fonts(&mut memory, &font);
+        load_program(&mut memory, program, &layout);
+
         System {
             memory,
             registers: [0; 16],
@@ -56,18 +91,76 @@ impl System {
             stack: VecDeque::with_capacity(24),
             delay_timer: 0,
             sound_timer: 0,
-            program_counter: 0x200,
+            program_counter: layout.entry_point,
 
+            framebuffer: Framebuffer::new(),
             display,
             input,
             sound,
 
             rng: rand::thread_rng(),
+
+            debug_map: DebugMap::new(),
+            quirks: Quirks::default(),
+            speed: DEFAULT_SPEED,
+            layout,
+            name: "alvin".to_string(),
+            recorder: None,
+            record_audio: false,
+            record_scale: DEFAULT_RECORD_SCALE,
         }
     }
 
+    pub fn set_debug_map(&mut self, debug_map: DebugMap) {
+        self.debug_map = debug_map;
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    /// How recordings are made: whether the beeper is saved alongside them, and how many times
+    /// larger than 128x64 their frames are.
+    pub fn set_recording(&mut self, audio: bool, scale: usize) {
+        self.record_audio = audio;
+        self.record_scale = scale;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.framebuffer.set_wrap(quirks.wrap_sprites);
+    }
+
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed.max(1);
+    }
+
+    /// Instructions executed per 60 Hz frame.
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn map_key(&mut self, key: Constant, keyboard: &str) -> bool {
+        self.input.map_key(key, keyboard)
+    }
+
+    pub fn set_scale(&mut self, scale: u32) {
+        self.display.set_scale(scale);
+    }
+
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.display.set_persistence(persistence);
+    }
+
+    pub fn set_upscaler(&mut self, upscaler: Upscaler) {
+        self.display.set_upscaler(upscaler);
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.display.set_palette(palette);
+    }
+
     pub fn run(&mut self, dump_state: bool) -> Result<(), ()> {
-        let mut tick_rate: Duration = Duration::from_millis(16);
         let mut running = true;
         let mut paused = false;
 
@@ -75,28 +168,55 @@ impl System {
             match self.input.handle_input() {
                 Some(InputAction::Quit) => running = false,
                 Some(InputAction::Reset) => {
-                    // We're going to need to reset program memory as well
-
-                    self.registers = [0; 16];
-                    self.address_register = 0x0;
-                    self.stack.clear();
-                    self.delay_timer = 0;
-                    self.sound_timer = 0;
-                    self.program_counter = 0x200;
-
-                    self.display.clear();
+                    self.reset();
+                    paused = false;
+                    self.display.set_status(None);
+                    self.display.show_message("RESET".to_string());
+                }
+                Some(InputAction::Pause) => {
+                    paused = !paused;
+                    self.display.set_status(if paused { Some("PAUSED".to_string()) } else { None });
+                }
+                Some(InputAction::DecreaseSpeed) => {
+                    let speed = self.speed.saturating_sub(1);
+                    self.set_speed(speed);
+                    self.display.show_message(format!("SPEED: {}", self.speed));
                 }
-                Some(InputAction::Pause) => paused = !paused,
-                Some(InputAction::DecreaseTick) => {
-                    if tick_rate.as_millis() >= 8 {
-                        tick_rate -= Duration::from_millis(4);
+                Some(InputAction::IncreaseSpeed) => {
+                    let speed = self.speed + 1;
+                    self.set_speed(speed);
+                    self.display.show_message(format!("SPEED: {}", self.speed));
+                }
+                Some(InputAction::NextPalette) => {
+                    let (name, palette) = self.display.palette().next_theme();
+                    self.display.set_palette(palette);
+                    self.display.show_message(format!("PALETTE: {}", name));
+                }
+                Some(InputAction::ToggleFullscreen) => self.display.toggle_fullscreen(),
+                Some(InputAction::Screenshot) => match self.screenshot() {
+                    Ok(paths) => {
+                        for path in paths {
+                            println!("Saved {}", path.display());
+                        }
+                        self.display.show_message("SCREENSHOT SAVED".to_string());
+                    }
+                    Err(error) => {
+                        println!("WARNING: {}", error);
+                        self.display.show_message("SCREENSHOT FAILED".to_string());
+                    }
+                },
+                Some(InputAction::Record) => {
+                    if self.recorder.is_some() {
+                        self.stop_recording();
+                        self.display.show_message("RECORDING STOPPED".to_string());
                     } else {
-                        tick_rate = Duration::from_millis(4);
+                        let target = format!("{}-{}.gif", self.name, timestamp(SystemTime::now()));
+                        match self.start_recording(&target) {
+                            Ok(()) => self.display.show_message("RECORDING".to_string()),
+                            Err(error) => println!("WARNING: {}", error),
+                        }
                     }
                 }
-                Some(InputAction::IncreaseTick) => {
-                    tick_rate += Duration::from_millis(4);
-                }
                 Some(InputAction::DebugInfo) => {
                     if !dump_state {
                         self.print_debug();
@@ -106,59 +226,213 @@ impl System {
             }
 
             if paused {
+                self.display.present(&self.framebuffer);
+                thread::sleep(FRAME_DURATION);
                 continue;
             }
 
-            if dump_state {
-                self.print_debug();
+            for _ in 0..self.speed {
+                if dump_state {
+                    self.print_debug();
+                }
+
+                // Halt rather than close the window, so the error can be seen and the ROM reset
+                if self.step().is_err() {
+                    paused = true;
+                    let status = format!("ERROR AT {:#05X}", self.program_counter);
+                    self.display.set_status(Some(status));
+                    break;
+                }
             }
 
-            let first_address = self.program_counter as usize;
-            let second_address = (self.program_counter + 1) as usize;
+            self.tick();
+        }
 
-            let first_byte = self.memory[first_address];
-            let second_byte = self.memory[second_address];
+        self.stop_recording();
+        Err(())
+    }
 
-            if let Ok(opcode) = Opcode::from(first_byte, second_byte) {
-                self.process_opcode(opcode)?;
-            }
+    /// Fetches, decodes and executes the instruction at the program counter.
+    pub fn step(&mut self) -> Result<(), ()> {
+        let (first_byte, second_byte) = self.fetch();
 
-            self.tick(tick_rate);
+        if let Ok(opcode) = Opcode::from(first_byte, second_byte) {
+            self.process_opcode(opcode)?;
         }
 
-        Err(())
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        // We're going to need to reset program memory as well
+
+        self.registers = [0; 16];
+        self.address_register = 0x0;
+        self.stack.clear();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.program_counter = self.layout.entry_point;
+
+        self.framebuffer = Framebuffer::new();
+        self.framebuffer.set_wrap(self.quirks.wrap_sprites);
+    }
+
+    pub fn poll_input(&mut self) -> Option<InputAction> {
+        self.input.handle_input()
+    }
+
+    pub fn current_opcode(&self) -> Option<Opcode> {
+        let (first_byte, second_byte) = self.fetch();
+        Opcode::from(first_byte, second_byte).ok()
+    }
+
+    /// The two bytes at the program counter, wrapping around the end of memory like every other
+    /// memory access.
+    fn fetch(&self) -> (Constant, Constant) {
+        (self.get_memory(self.program_counter), self.get_memory(self.program_counter.wrapping_add(1)))
+    }
+
+    pub fn program_counter(&self) -> Address {
+        self.program_counter
+    }
+
+    pub fn registers(&self) -> &[Constant; 16] {
+        &self.registers
+    }
+
+    pub fn address_register(&self) -> Address {
+        self.address_register
+    }
+
+    pub fn delay_timer(&self) -> Constant {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> Constant {
+        self.sound_timer
+    }
+
+    /// Addresses of the pending calls, innermost first.
+    pub fn call_stack(&self) -> &VecDeque<Address> {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &[Constant] {
+        &self.memory
+    }
+
+    /// Starts capturing every frame to `target`, as `Recorder::start` describes.
+    pub fn start_recording(&mut self, target: &str) -> Result<(), String> {
+        self.stop_recording();
+
+        let audio = if self.record_audio { Some(audio_path(target, &self.name, &timestamp(SystemTime::now()))) } else { None };
+        let recorder = Recorder::start(target, &self.display.palette(), audio.as_deref(), self.record_scale)?;
+
+        let (width, height) = recorder.frame_size();
+        println!("Recording {}x{} frames at 60 fps to {}", width, height, target.trim_start_matches('|'));
+        if let Some(audio) = audio {
+            println!("Recording sound to {}", audio.display());
+        }
+
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok(()) => println!("Stopped recording"),
+                Err(error) => println!("WARNING: could not finish the recording: {}", error),
+            }
+        }
+    }
+
+    /// Saves the screen as PNGs at its own resolution and as large as it's shown, returning
+    /// where they went.
+    pub fn screenshot(&self) -> Result<Vec<PathBuf>, String> {
+        let scale = self.display.pixel_scale(&self.framebuffer);
+        screenshot::save(&self.framebuffer, &self.display.palette(), scale, &self.name)
+    }
+
+    pub fn debug_map(&self) -> &DebugMap {
+        &self.debug_map
     }
 
     fn print_debug(&mut self) {
-        print!("PC[{:#04x}]\tDELAY[{}]\tSOUND[{}]\tI[{:#03x}]", self.program_counter, self.delay_timer, self.sound_timer, self.address_register);
+        print!("PC[{:#04x}]", self.program_counter);
+        if let Some(symbol) = self.debug_map.symbolize(self.program_counter) {
+            print!("<{}>", symbol);
+        }
+
+        print!("\tDELAY[{}]\tSOUND[{}]\tI[{:#03x}]", self.delay_timer, self.sound_timer, self.address_register);
         for i in 0x0..0x10 {
-            print!("\tV{:X}[{}]", i, self.get_register(i));
+            match self.debug_map.alias(i) {
+                Some(alias) => print!("\tV{:X}:{}[{}]", i, alias, self.get_register(i)),
+                None => print!("\tV{:X}[{}]", i, self.get_register(i)),
+            }
         }
 
-        let first_address = self.program_counter as usize;
-        let second_address = (self.program_counter + 1) as usize;
+        let (first_byte, second_byte) = self.fetch();
+
+        let debug_map = &self.debug_map;
+        let name = |address: Address| debug_map.symbolize(address).unwrap_or_else(|| hex_address(address));
+        match Opcode::from(first_byte, second_byte) {
+            Ok(op) => print!("\t{}", op.render(Syntax::Classic, &name)),
+            Err(_) => print!("\t{:02x}{:02x}", first_byte, second_byte),
+        }
 
-        let first_byte = self.memory[first_address];
-        let second_byte = self.memory[second_address];
+        if let Some(source) = self.debug_map.source_line(self.program_counter) {
+            print!("\t{}:{}", source.file, source.line);
+            if let Some(text) = self.debug_map.source_text(self.program_counter) {
+                print!("\t{}", text);
+            }
+        }
 
-        let op = Opcode::from(first_byte, second_byte).unwrap();
-        println!("\t{:?}", op);
+        println!();
     }
 
     fn process_opcode(&mut self, opcode: Opcode) -> Result<(), ()> {
         match opcode {
             Opcode::Call(_) => {
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Clear => {
-                self.display.clear();
-                self.program_counter += WORD_SIZE;
+                self.framebuffer.clear();
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::LowResolution => {
+                self.framebuffer.set_high_resolution(false);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::HighResolution => {
+                self.framebuffer.set_high_resolution(true);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::ScrollDown(rows) => {
+                self.framebuffer.scroll(0, rows as isize);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::ScrollUp(rows) => {
+                self.framebuffer.scroll(0, -(rows as isize));
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::ScrollRight => {
+                self.framebuffer.scroll(SCROLL_DISTANCE, 0);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::ScrollLeft => {
+                self.framebuffer.scroll(-SCROLL_DISTANCE, 0);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::SelectPlanes(planes) => {
+                self.framebuffer.select_planes(planes);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Return => {
                 if let Some(address) = self.stack.pop_front() {
-                    self.program_counter = address + WORD_SIZE;
+                    self.program_counter = address.wrapping_add(WORD_SIZE);
                 } else {
-                    println!("NOWHERE TO RETURN");
+                    eprintln!("NOWHERE TO RETURN");
                     return Err(());
                 }
             }
@@ -171,28 +445,28 @@ impl System {
             }
             Opcode::SkipEq(register, constant) => {
                 if self.get_register(register) == constant {
-                    self.program_counter += 2 * WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(2 * WORD_SIZE);
                 } else {
-                    self.program_counter += WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
                 }
             }
             Opcode::SkipNEq(register, constant) => {
                 if self.get_register(register) != constant {
-                    self.program_counter += 2 * WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(2 * WORD_SIZE);
                 } else {
-                    self.program_counter += WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
                 }
             }
             Opcode::SkipEqReg(first, second) => {
                 if self.get_register(first) == self.get_register(second) {
-                    self.program_counter += 2 * WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(2 * WORD_SIZE);
                 } else {
-                    self.program_counter += WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
                 }
             }
             Opcode::Set(register, constant) => {
                 self.set_register(register, constant);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::AddAssign(register, constant) => {
                 let value = self.get_register(register);
@@ -204,30 +478,42 @@ impl System {
                 };
 
                 self.set_register(register, result);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Copy(to, from) => {
                 let from_value = self.get_register(from);
                 self.set_register(to, from_value);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Or(first, second) => {
                 let first_value = self.get_register(first);
                 let second_value = self.get_register(second);
                 self.set_register(first, first_value | second_value);
-                self.program_counter += WORD_SIZE;
+                if self.quirks.logic_resets_vf {
+                    self.set_flag_register(0x0);
+                }
+
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::And(first, second) => {
                 let first_value = self.get_register(first);
                 let second_value = self.get_register(second);
                 self.set_register(first, first_value & second_value);
-                self.program_counter += WORD_SIZE;
+                if self.quirks.logic_resets_vf {
+                    self.set_flag_register(0x0);
+                }
+
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Xor(first, second) => {
                 let first_value = self.get_register(first);
                 let second_value = self.get_register(second);
                 self.set_register(first, first_value ^ second_value);
-                self.program_counter += WORD_SIZE;
+                if self.quirks.logic_resets_vf {
+                    self.set_flag_register(0x0);
+                }
+
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::AddAssignReg(first, second) => {
                 let first_value = self.get_register(first);
@@ -244,7 +530,7 @@ impl System {
                 self.set_register(first, result);
 
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::SubAssignReg(first, second) => {
                 let first_value = self.get_register(first);
@@ -258,19 +544,16 @@ impl System {
 
                 self.set_register(first, first_value.wrapping_sub(second_value));
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::ShiftRight(first, second) => {
-                let original_value = self.get_register(second);
+                let original_value = self.get_register(self.shift_source(first, second));
                 let lowest_bit = original_value & 0x1;
 
-                let value = original_value >> 1;
-
-                self.set_register(first, value);
-                self.set_register(second, value);
+                self.set_register(first, original_value >> 1);
                 self.set_flag_register(lowest_bit);
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Subtract(first, second) => {
                 let first_value = self.get_register(first);
@@ -284,33 +567,36 @@ impl System {
 
                 self.set_register(first, second_value.wrapping_sub(first_value));
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::ShiftLeft(first, second) => {
-                let original_value = self.get_register(second);
-                let highest_bit = original_value & 0x8;
-
-                let value = original_value << 1;
+                let original_value = self.get_register(self.shift_source(first, second));
+                let highest_bit = original_value >> 7;
 
-                self.set_register(first, value);
-                self.set_register(second, value);
+                self.set_register(first, original_value << 1);
                 self.set_flag_register(highest_bit);
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::SkipNEqReg(first, second) => {
                 if self.get_register(first) != self.get_register(second) {
-                    self.program_counter += 2 * WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(2 * WORD_SIZE);
                 } else {
-                    self.program_counter += WORD_SIZE;
+                    self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
                 }
             }
             Opcode::SetAddressReg(address) => {
                 self.address_register = address;
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::JumpOffset(address) => {
-                self.program_counter = address + self.get_register(0x0) as u16;
+                let offset_register = if self.quirks.jump_uses_vx {
+                    ((address >> 8) & 0xF) as Register
+                } else {
+                    0x0
+                };
+
+                self.program_counter = address.wrapping_add(self.get_register(offset_register) as u16);
             }
             Opcode::SetRand(register, constant) => {
                 let range = Range::new(0, constant);
@@ -318,105 +604,82 @@ impl System {
 
                 self.set_register(register, random_value);
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
-            Opcode::Draw(first, second, constant) => {
-                let mut collision = false;
-
-                let mut x = self.get_register(second);
-                for i in self.address_register..(self.address_register + constant as u16) {
-                    let sprite = self.get_memory(i);
+            Opcode::Draw(first, second, height) => {
+                let x = self.get_register(first);
+                let y = self.get_register(second);
 
-                    let y = self.get_register(first);
+                // DXY0 draws a 16x16 sprite, two bytes a row
+                let bytes_per_plane = if height == 0 { LARGE_SPRITE_SIZE } else { height as u16 };
+                let length = bytes_per_plane * self.framebuffer.selected_planes() as u16;
+                let rows: Vec<Constant> = (0..length)
+                    .map(|offset| self.get_memory(self.address_register.wrapping_add(offset)))
+                    .collect();
 
-                    collision |= self.display.draw(x, y, sprite);
-
-                    if (x as usize) == self.display.screen_dimensions().0 - 1 {
-                        x = 0;
-                    } else {
-                        x += 1;
-                    }
-                }
-
-                if collision {
-                    self.set_flag_register(0x1);
+                if height == 0 {
+                    // SCHIP counts the rows which collided in high resolution
+                    let collided_rows = self.framebuffer.draw_large_sprite(x, y, &rows);
+                    let high_resolution = self.framebuffer.dimensions() == HIGH_RESOLUTION;
+                    self.set_flag_register(if high_resolution { collided_rows } else { collided_rows.min(1) });
                 } else {
-                    self.set_flag_register(0x0);
+                    let collision = self.framebuffer.draw_sprite(x, y, &rows);
+                    self.set_flag_register(collision as Constant);
                 }
 
-                self.display.render();
-
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::SkipKeyPress(register) => {
                 let expected_key = self.get_register(register);
                 if let Some(key) = self.input.key_pressed() {
                     if expected_key == key {
-                        self.program_counter += 2 * WORD_SIZE;
+                        self.program_counter = self.program_counter.wrapping_add(2 * WORD_SIZE);
                         return Ok(());
                     }
                 }
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::SkipNoKeyPress(register) => {
                 let expected_key = self.get_register(register);
                 if let Some(key) = self.input.key_pressed() {
                     if expected_key != key {
-                        self.program_counter += 2 * WORD_SIZE;
+                        self.program_counter = self.program_counter.wrapping_add(2 * WORD_SIZE);
                         return Ok(());
                     }
                 }
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::StoreDelayTimer(register) => {
                 let delay = self.delay_timer;
                 self.set_register(register, delay);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::StoreKeypress(register) => {
                 let pressed_key = self.input.get_key();
                 self.set_register(register, pressed_key);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::SetDelayTimer(register) => {
                 self.delay_timer = self.get_register(register);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::SetSoundTimer(register) => {
                 self.sound_timer = self.get_register(register);
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::IncrementAddressReg(register) => {
-                self.address_register += self.get_register(register) as u16;
-                self.program_counter += WORD_SIZE;
+                self.address_register = self.address_register.wrapping_add(self.get_register(register) as u16);
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::StoreSpriteAddress(register) => {
-                let value = self.get_register(register) as u16;
-                self.address_register = match value {
-                    0x0 => 0x000,
-                    0x1 => 0x005,
-                    0x2 => 0x00A,
-                    0x3 => 0x00F,
-
-                    0x4 => 0x014,
-                    0x5 => 0x019,
-                    0x6 => 0x024,
-                    0x7 => 0x029,
-
-                    0x8 => 0x034,
-                    0x9 => 0x039,
-                    0xA => 0x044,
-                    0xB => 0x049,
-
-                    0xC => 0x054,
-                    0xD => 0x059,
-                    0xE => 0x064,
-                    0xF => 0x069,
-                    _ => 0x000,
-                };
-                self.program_counter += WORD_SIZE;
+                self.address_register = Font::glyph_address(self.get_register(register));
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
+            }
+            Opcode::StoreLargeSpriteAddress(register) => {
+                self.address_register = Font::large_glyph_address(self.get_register(register));
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::BinaryCodedDecimal(register) => {
                 let memory_location = self.address_register;
@@ -427,62 +690,76 @@ impl System {
                 let hundreds = (value % 100) % 10;
 
                 self.set_memory(memory_location, ones as u8);
-                self.set_memory(memory_location + 1, tens as u8);
-                self.set_memory(memory_location + 2, hundreds as u8);
+                self.set_memory(memory_location.wrapping_add(1), tens as u8);
+                self.set_memory(memory_location.wrapping_add(2), hundreds as u8);
 
-                self.program_counter += WORD_SIZE;
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Dump(register) => {
                 for i in 0..(register + 1) {
-                    let memory_location = self.address_register;
+                    let memory_location = self.address_register.wrapping_add(i as u16);
                     let value = self.get_register(i);
 
                     self.set_memory(memory_location, value);
-                    self.address_register += 1;
                 }
-                self.program_counter += WORD_SIZE;
+
+                if self.quirks.load_store_increments_i {
+                    self.address_register = self.address_register.wrapping_add(register as u16 + 1);
+                }
+
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
             Opcode::Load(register) => {
                 for i in 0..(register + 1) {
-                    let memory_location = self.address_register;
+                    let memory_location = self.address_register.wrapping_add(i as u16);
                     let value = self.get_memory(memory_location);
 
                     self.set_register(i, value);
-                    self.address_register += 1;
                 }
-                self.program_counter += WORD_SIZE;
+
+                if self.quirks.load_store_increments_i {
+                    self.address_register = self.address_register.wrapping_add(register as u16 + 1);
+                }
+
+                self.program_counter = self.program_counter.wrapping_add(WORD_SIZE);
             }
         }
 
         Ok(())
     }
 
-    fn tick(&mut self, tick_rate: Duration) {
-        let mut active_delay = self.delay_timer > 0;
-        let mut active_sound = self.sound_timer > 0;
+    /// Counts the timers down by one 60 Hz frame and waits out the rest of it.
+    pub fn tick(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.capture(&self.framebuffer, &self.display.palette(), self.sound_timer > 0) {
+                println!("WARNING: stopped recording: {}", error);
 
-        if active_sound {
-            self.sound.play()
+                // Still finishing what was recorded, so the file is left readable
+                if let Some(Err(error)) = self.recorder.take().map(Recorder::finish) {
+                    println!("WARNING: could not finish the recording: {}", error);
+                }
+            }
         }
 
-        while active_delay | active_sound {
-            if active_delay && self.delay_timer <= 0 {
-                self.delay_timer = 0;
-                active_delay = false;
-            } else if active_delay {
-                self.delay_timer -= 1;
-            }
+        self.delay_timer = self.delay_timer.saturating_sub(1);
 
-            if active_sound && self.sound_timer <= 0 {
-                self.sound_timer = 0;
-                self.sound.stop();
-                active_sound = false;
-            } else if active_sound {
-                self.sound_timer -= 1;
-            }
+        if self.sound_timer > 0 {
+            self.sound.play();
+            self.sound_timer -= 1;
+        } else {
+            self.sound.stop();
         }
 
-        thread::sleep(tick_rate);
+        self.display.present(&self.framebuffer);
+        thread::sleep(FRAME_DURATION);
+    }
+
+    fn shift_source(&self, first: Register, second: Register) -> Register {
+        if self.quirks.shift_uses_vy {
+            second
+        } else {
+            first
+        }
     }
 
     fn get_register(&self, register: Register) -> Constant {
@@ -497,11 +774,94 @@ impl System {
         self.registers[0xF] = value;
     }
 
-    fn get_memory(&mut self, address: Address) -> Constant {
-        self.memory[address as usize]
+    // Hosts with less memory than addresses can reach see it repeated, as on the VIP
+    fn get_memory(&self, address: Address) -> Constant {
+        self.memory[address as usize % self.memory.len()]
     }
 
     fn set_memory(&mut self, address: Address, value: Constant) {
-        self.memory[address as usize] = value;
+        let length = self.memory.len();
+        self.memory[address as usize % length] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    #[test]
+    fn instructions_are_fetched_around_the_end_of_memory() {
+        let platform = Platform::Chip8;
+        let mut system = System::headless(&[], Layout::for_platform(platform), Font::for_platform(platform));
+
+        // `6A` in the last byte, and the top of the built in 0 glyph after it
+        let last = system.memory.len() - 1;
+        system.memory[last] = 0x6A;
+        system.program_counter = last as Address;
+
+        assert!(system.current_opcode() == Some(Opcode::Set(0xA, system.memory[0])));
+        system.step().unwrap();
+        assert_eq!(system.registers()[0xA], system.memory[0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn program_counters_wrap_around_the_end_of_the_address_space() {
+        let platform = Platform::XoChip;
+        let mut system = System::headless(&[], Layout::for_platform(platform), Font::for_platform(platform));
+        let first = system.memory[0];
+
+        system.memory[0xFFFF] = 0x6A;
+        system.program_counter = 0xFFFF;
+        system.step().unwrap();
+        assert_eq!(system.registers()[0xA], first);
+        assert_eq!(system.program_counter(), 0x0001);
+
+        // Skipping from the last instruction lands past the start of memory
+        system.memory[0xFFFE] = 0x3A;
+        system.memory[0xFFFF] = first;
+        system.program_counter = 0xFFFE;
+        system.step().unwrap();
+        assert_eq!(system.program_counter(), 0x0002);
+
+        // Stores running off the end of memory carry on from the start
+        system.memory[0xFFFE] = 0xF1;
+        system.memory[0xFFFF] = 0x55;
+        system.set_register(0x1, 0xAB);
+        system.program_counter = 0xFFFE;
+        system.address_register = 0xFFFF;
+        system.step().unwrap();
+        assert_eq!(system.memory[0], 0xAB);
+        assert_eq!(system.program_counter(), 0x0000);
+    }
+
+    #[test]
+    fn large_sprites_count_colliding_rows_in_high_resolution() {
+        let platform = Platform::SuperChip;
+        let mut program = vec![
+            0x00, 0xFF, // HIGH
+            0xA2, 0x10, // LD I, sprite
+            0xD0, 0x10, // DRW V0, V0, 0
+            0xD0, 0x10,
+            0x00, 0xFE, // LOW
+            0xD0, 0x10,
+            0xD0, 0x10,
+            0x12, 0x0E,
+        ];
+        program.extend_from_slice(&[0xFF; 32]);
+        let mut system = System::headless(&program, Layout::for_platform(platform), Font::for_platform(platform));
+
+        for _ in 0..3 {
+            system.step().unwrap();
+        }
+        assert_eq!(system.framebuffer.pixels().iter().filter(|pixel| **pixel != 0).count(), 16 * 16);
+
+        system.step().unwrap();
+        assert_eq!(system.registers()[0xF], 16);
+
+        for _ in 0..3 {
+            system.step().unwrap();
+        }
+        assert_eq!(system.registers()[0xF], 1);
+    }
+}
@@ -0,0 +1,302 @@
+/// The standard CHIP-8 screen.
+pub const LOW_RESOLUTION: (usize, usize) = (64, 32);
+/// The SCHIP and XO-CHIP high resolution screen.
+pub const HIGH_RESOLUTION: (usize, usize) = (128, 64);
+/// XO-CHIP draws to up to two bit planes, the first being the only one on other platforms.
+pub const PLANES: u8 = 2;
+
+/// The emulated screen, kept apart from any frontend so SDL, the debugger and recordings all see
+/// the same pixels.
+///
+/// Each pixel is a bitmask of the planes it's lit on.
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    planes: u8,
+    wrap: bool,
+    version: u64,
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        let (width, height) = LOW_RESOLUTION;
+
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            planes: 0b01,
+            wrap: false,
+            version: 0,
+        }
+    }
+
+    /// Whether sprites running off an edge continue on the opposite one rather than being cut off.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Which planes `draw_sprite` and `clear` affect, as a bitmask.
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANES) - 1);
+    }
+
+    /// How many planes are selected, and so how many sprites `draw_sprite` expects.
+    pub fn selected_planes(&self) -> u8 {
+        self.planes.count_ones() as u8
+    }
+
+    /// Switches between 64x32 and 128x64, clearing the screen as SCHIP does.
+    pub fn set_high_resolution(&mut self, high: bool) {
+        let (width, height) = if high { HIGH_RESOLUTION } else { LOW_RESOLUTION };
+
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+        self.version += 1;
+    }
+
+    /// Changes whenever the pixels do, so frontends can skip redrawing an unchanged screen.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// The planes lit at each pixel, a row at a time from the top left.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn clear(&mut self) {
+        let planes = self.planes;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+
+        self.version += 1;
+    }
+
+    /// XORs an 8 pixel wide sprite onto the selected planes with its top left corner at `(x, y)`,
+    /// returning whether any lit pixel was turned off.
+    ///
+    /// `rows` holds the sprite for each selected plane in turn, first plane first. The position
+    /// always wraps around the screen, but the sprite itself is clipped at the edges unless
+    /// wrapping is turned on.
+    pub fn draw_sprite(&mut self, x: u8, y: u8, rows: &[u8]) -> bool {
+        let rows: Vec<u16> = rows.iter().map(|row| (*row as u16) << 8).collect();
+        self.draw(x, y, &rows, 8) > 0
+    }
+
+    /// XORs a 16x16 SCHIP sprite onto the selected planes like `draw_sprite`, returning how many
+    /// of its rows turned a lit pixel off.
+    ///
+    /// Each row is two bytes, left half first, so a plane's sprite takes 32 bytes.
+    pub fn draw_large_sprite(&mut self, x: u8, y: u8, rows: &[u8]) -> u8 {
+        let rows: Vec<u16> = rows.chunks(2)
+            .map(|row| (row[0] as u16) << 8 | *row.get(1).unwrap_or(&0) as u16)
+            .collect();
+        self.draw(x, y, &rows, 16) as u8
+    }
+
+    /// Draws `width` pixels from the top of each row, returning how many rows collided.
+    fn draw(&mut self, x: u8, y: u8, rows: &[u16], width: usize) -> usize {
+        let x = x as usize % self.width;
+        let y = y as usize % self.height;
+
+        let planes: Vec<u8> = (0..PLANES).map(|plane| 1 << plane).filter(|plane| self.planes & plane != 0).collect();
+        if planes.is_empty() {
+            return 0;
+        }
+
+        let height = rows.len() / planes.len();
+        let mut collided = vec![false; height];
+        self.version += 1;
+
+        for (plane, rows) in planes.iter().zip(rows.chunks(height.max(1))) {
+            for (row, sprite) in rows.iter().enumerate() {
+                for column in 0..width {
+                    if sprite & (0x8000 >> column) == 0 {
+                        continue;
+                    }
+
+                    let (pixel_x, pixel_y) = (x + column, y + row);
+                    if !self.wrap && (pixel_x >= self.width || pixel_y >= self.height) {
+                        continue;
+                    }
+
+                    let pixel = &mut self.pixels[(pixel_y % self.height) * self.width + pixel_x % self.width];
+                    collided[row] |= *pixel & plane != 0;
+                    *pixel ^= plane;
+                }
+            }
+        }
+
+        collided.iter().filter(|collided| **collided).count()
+    }
+
+    /// Moves the selected planes `dx` pixels right and `dy` pixels down, as the SCHIP and XO-CHIP
+    /// scroll instructions do, leaving the pixels scrolled in unlit.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let planes = self.planes;
+        let before = self.pixels.clone();
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let scrolled = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    before[(from_y * width + from_x) as usize] & planes
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | scrolled;
+            }
+        }
+
+        self.version += 1;
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The planes lit at `(x, y)`.
+    fn at(framebuffer: &Framebuffer, x: usize, y: usize) -> u8 {
+        framebuffer.pixels()[y * framebuffer.dimensions().0 + x]
+    }
+
+    /// The lit pixels, as `(x, y)` pairs from the top left.
+    fn lit(framebuffer: &Framebuffer) -> Vec<(usize, usize)> {
+        let (width, _) = framebuffer.dimensions();
+        framebuffer.pixels().iter().enumerate()
+            .filter(|(_, pixel)| **pixel != 0)
+            .map(|(index, _)| (index % width, index / width))
+            .collect()
+    }
+
+    #[test]
+    fn sprites_are_xored_and_report_turning_pixels_off() {
+        let mut framebuffer = Framebuffer::new();
+
+        assert!(!framebuffer.draw_sprite(2, 1, &[0b1100_0000, 0b0100_0000]));
+        assert_eq!(lit(&framebuffer), vec![(2, 1), (3, 1), (3, 2)]);
+
+        // Overlapping only at (3, 2), which is turned off
+        assert!(framebuffer.draw_sprite(3, 2, &[0b1000_0000]));
+        assert_eq!(lit(&framebuffer), vec![(2, 1), (3, 1)]);
+
+        assert!(framebuffer.draw_sprite(2, 1, &[0b1100_0000]));
+        assert!(lit(&framebuffer).is_empty());
+    }
+
+    #[test]
+    fn sprites_are_clipped_at_the_edges_unless_wrapping() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(62, 31, &[0xFF, 0xFF]);
+        assert_eq!(lit(&framebuffer), vec![(62, 31), (63, 31)]);
+
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_wrap(true);
+        framebuffer.draw_sprite(62, 31, &[0b1110_0000, 0b1000_0000]);
+        assert_eq!(lit(&framebuffer), vec![(62, 0), (0, 31), (62, 31), (63, 31)]);
+    }
+
+    #[test]
+    fn positions_off_the_screen_wrap_around_it() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(64 + 5, 32 + 3, &[0x80]);
+        assert_eq!(lit(&framebuffer), vec![(5, 3)]);
+    }
+
+    #[test]
+    fn large_sprites_are_16_pixels_square_and_count_colliding_rows() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_high_resolution(true);
+
+        let mut sprite = [0; 32];
+        sprite[0] = 0x80;
+        sprite[1] = 0x01;
+        sprite[30] = 0xFF;
+        sprite[31] = 0xFF;
+
+        assert_eq!(framebuffer.draw_large_sprite(10, 20, &sprite), 0);
+        let pixels = lit(&framebuffer);
+        assert_eq!(pixels.len(), 2 + 16);
+        assert!(pixels.contains(&(10, 20)) && pixels.contains(&(25, 20)));
+        assert!((10..26).all(|x| pixels.contains(&(x, 35))));
+
+        // Two rows collide, however many pixels in them do
+        assert_eq!(framebuffer.draw_large_sprite(10, 20, &sprite), 2);
+        assert!(lit(&framebuffer).is_empty());
+    }
+
+    #[test]
+    fn sprites_take_a_set_of_rows_for_each_selected_plane() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.select_planes(0b11);
+        assert_eq!(framebuffer.selected_planes(), 2);
+
+        framebuffer.draw_sprite(0, 0, &[0b1100_0000, 0b0110_0000]);
+        assert_eq!((at(&framebuffer, 0, 0), at(&framebuffer, 1, 0), at(&framebuffer, 2, 0)), (0b01, 0b11, 0b10));
+
+        framebuffer.select_planes(0b10);
+        framebuffer.clear();
+        assert_eq!((at(&framebuffer, 0, 0), at(&framebuffer, 1, 0), at(&framebuffer, 2, 0)), (0b01, 0b01, 0));
+
+        framebuffer.select_planes(0);
+        assert!(!framebuffer.draw_sprite(0, 0, &[0xFF]));
+        assert_eq!(lit(&framebuffer), vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn scrolling_moves_the_selected_planes_and_leaves_unlit_pixels_behind() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_high_resolution(true);
+        framebuffer.draw_sprite(0, 0, &[0x80]);
+        framebuffer.draw_sprite(127, 63, &[0x80]);
+
+        framebuffer.scroll(0, 4);
+        assert_eq!(lit(&framebuffer), vec![(0, 4)]);
+
+        framebuffer.scroll(4, 0);
+        assert_eq!(lit(&framebuffer), vec![(4, 4)]);
+
+        framebuffer.scroll(-4, -1);
+        assert_eq!(lit(&framebuffer), vec![(0, 3)]);
+
+        // Only the selected plane moves
+        framebuffer.select_planes(0b10);
+        framebuffer.draw_sprite(0, 3, &[0x80]);
+        framebuffer.scroll(1, 0);
+        assert_eq!((at(&framebuffer, 0, 3), at(&framebuffer, 1, 3)), (0b01, 0b10));
+    }
+
+    #[test]
+    fn changing_resolution_clears_the_screen() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(0, 0, &[0xFF]);
+        let version = framebuffer.version();
+
+        framebuffer.set_high_resolution(true);
+        assert_eq!(framebuffer.dimensions(), HIGH_RESOLUTION);
+        assert!(lit(&framebuffer).is_empty());
+        assert!(framebuffer.version() != version);
+
+        framebuffer.set_high_resolution(false);
+        assert_eq!(framebuffer.dimensions(), LOW_RESOLUTION);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{
+    analyzer::analyze,
+    loader::size_warning,
+    memory::PROGRAM_START,
+    opcode::{hex_address, Opcode},
+    rom_db::{sha1, RomEntry},
+};
+
+/// Prints what's worth knowing about a ROM at a glance.
+pub fn print_info(rom: &[u8], entry: Option<&RomEntry>) {
+    let report = analyze(rom);
+
+    println!("Size:        {} bytes", rom.len());
+    println!("SHA-1:       {}", sha1(rom));
+    println!("Platform:    {}", report.platform);
+
+    let jump = match rom {
+        [first, second, ..] => match Opcode::from(*first, *second) {
+            Ok(Opcode::Goto(target)) => format!(", jumps to {}", hex_address(target)),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    };
+    println!("Entry point: {}{}", hex_address(PROGRAM_START), jump);
+
+    match entry {
+        Some(entry) => {
+            let title = entry.title.as_deref().unwrap_or("untitled");
+            match &entry.author {
+                Some(author) => println!("Title:       {} by {}", title, author),
+                None => println!("Title:       {}", title),
+            }
+
+            if let Some(description) = &entry.description {
+                println!("Description: {}", description);
+            }
+        }
+        None => println!("Title:       not in the database"),
+    }
+
+    let opcodes: Vec<_> = report.opcodes.iter().cloned().collect();
+    println!("Opcodes:     {}", opcodes.join(" "));
+
+    if let Some(warning) = size_warning(rom, report.platform) {
+        println!("Too large:   {}", warning);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use sdl2::{
     self,
     event::Event,
@@ -6,7 +8,9 @@ use sdl2::{
 };
 
 pub struct Input {
-    event_pump: EventPump
+    /// Where key presses come from, missing when running without a window.
+    event_pump: Option<EventPump>,
+    extra_keys: HashMap<Keycode, u8>,
 }
 
 impl Input {
@@ -14,12 +18,39 @@ impl Input {
         let event_pump = sdl_context.event_pump().unwrap();
 
         Input {
-            event_pump
+            event_pump: Some(event_pump),
+            extra_keys: HashMap::new(),
+        }
+    }
+
+    /// Input which never sees a key pressed, for running the emulator in tests.
+    #[cfg(test)]
+    pub fn headless() -> Input {
+        Input {
+            event_pump: None,
+            extra_keys: HashMap::new(),
+        }
+    }
+
+    /// Makes the keyboard key named `keyboard` press `key` as well, returning false if there's no such key.
+    pub fn map_key(&mut self, key: u8, keyboard: &str) -> bool {
+        match Keycode::from_name(keyboard) {
+            Some(keycode) => {
+                self.extra_keys.insert(keycode, key);
+                true
+            }
+            None => false,
         }
     }
 
+    fn lookup(&self, keycode: Keycode) -> Option<u8> {
+        self.extra_keys.get(&keycode).cloned().or_else(|| key_map(keycode))
+    }
+
     pub fn handle_input(&mut self) -> Option<InputAction> {
-        for event in self.event_pump.poll_iter() {
+        let event_pump = self.event_pump.as_mut()?;
+
+        for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     return Some(InputAction::Quit);
@@ -37,10 +68,22 @@ impl Input {
                     return Some(InputAction::Pause);
                 }
                 Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } => {
-                    return Some(InputAction::DecreaseTick);
+                    return Some(InputAction::DecreaseSpeed);
                 }
                 Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } => {
-                    return Some(InputAction::IncreaseTick);
+                    return Some(InputAction::IncreaseSpeed);
+                }
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                    return Some(InputAction::NextPalette);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                    return Some(InputAction::ToggleFullscreen);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    return Some(InputAction::Record);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    return Some(InputAction::Screenshot);
                 }
                 Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
                     return Some(InputAction::DebugInfo);
@@ -56,11 +99,13 @@ impl Input {
     }
 
     pub fn key_pressed(&mut self) -> Option<u8> {
-        for event in self.event_pump.wait_timeout_iter(10) {
+        let event_pump = self.event_pump.as_mut()?;
+
+        for event in event_pump.wait_timeout_iter(10) {
             match event {
                 Event::KeyDown { keycode, .. } | Event::KeyUp { keycode, .. } => {
                     if let Some(key) = keycode {
-                        return key_map(key);
+                        return self.lookup(key);
                     }
                 }
                 _ => {}
@@ -72,10 +117,12 @@ impl Input {
 
     pub fn get_key(&mut self) -> u8 {
         loop {
-            match self.event_pump.wait_event() {
+            let event_pump = self.event_pump.as_mut().expect("there is no keyboard to wait for a key on");
+
+            match event_pump.wait_event() {
                 Event::KeyDown { keycode, .. } | Event::KeyUp { keycode, .. } => {
                     if let Some(key) = keycode {
-                        if let Some(key_constant) = key_map(key) {
+                        if let Some(key_constant) = self.lookup(key) {
                             return key_constant;
                         }
                     }
@@ -90,8 +137,12 @@ pub enum InputAction {
     Quit,
     Reset,
     Pause,
-    DecreaseTick,
-    IncreaseTick,
+    DecreaseSpeed,
+    IncreaseSpeed,
+    NextPalette,
+    ToggleFullscreen,
+    Screenshot,
+    Record,
     DebugInfo,
 }
 
@@ -0,0 +1,255 @@
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cartridge::{self, GIF_MAGIC},
+    emulator::Address,
+    platform::Platform,
+    rom_db::RomEntry,
+};
+
+const HP48_HEADER: &[u8] = b"HPHP48-";
+const HP48_STRING_PROLOG: [u8; 2] = [0x2C, 0x2A];
+const HP48_DATA_OFFSET: usize = 13;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Binary,
+    HexText,
+    Hp48String,
+    OctoCartridge,
+}
+
+/// A program read from disk, along with the platform its file format implies, if any.
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    pub format: Format,
+    pub platform: Option<Platform>,
+    /// Settings which came with the program, as Octo cartridges carry.
+    pub settings: Option<RomEntry>,
+}
+
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    Empty(PathBuf),
+    TooLarge { path: PathBuf, size: usize, limit: usize, platform: Option<Platform> },
+    InvalidHex { path: PathBuf, line: usize, token: String },
+    InvalidHp48(PathBuf, &'static str),
+    InvalidCartridge(PathBuf, String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(path, error) => write!(f, "could not read {}: {}", path.display(), error),
+            LoadError::Empty(path) => write!(f, "{} is empty", path.display()),
+            LoadError::TooLarge { path, size, limit, platform: Some(platform) } => write!(
+                f,
+                "{} is {} bytes, but {} programs can be at most {} bytes",
+                path.display(), size, platform, limit
+            ),
+            LoadError::TooLarge { path, size, limit, platform: None } => write!(
+                f,
+                "{} is {} bytes, but only {} bytes of memory are free for programs",
+                path.display(), size, limit
+            ),
+            LoadError::InvalidHex { path, line, token } => write!(
+                f,
+                "{}:{}: `{}` is not a hex byte",
+                path.display(), line, token
+            ),
+            LoadError::InvalidHp48(path, reason) => write!(f, "{} is not a valid HP48 string: {}", path.display(), reason),
+            LoadError::InvalidCartridge(path, reason) => write!(f, "{} is not a valid Octo cartridge: {}", path.display(), reason),
+        }
+    }
+}
+
+/// Reads a whole ROM, accepting raw binaries (`.ch8`, `.sc8`, `.xo8`), hex text dumps,
+/// SCHIP binaries wrapped in an HP48 string and Octo cartridge GIFs.
+///
+/// ROMs which wouldn't fit between `load_address` and the end of `memory_size` bytes of memory
+/// are rejected.
+pub fn load(path: &Path, memory_size: usize, load_address: Address) -> Result<Rom, LoadError> {
+    let contents = fs::read(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let mut platform = match extension.as_deref() {
+        Some("sc8") => Some(Platform::SuperChip),
+        Some("xo8") => Some(Platform::XoChip),
+        _ => None,
+    };
+
+    let hex_extension = matches!(extension.as_deref(), Some("hex") | Some("txt"));
+
+    let mut settings = None;
+    let (bytes, format) = if contents.starts_with(GIF_MAGIC) {
+        let (bytes, options) = cartridge::load(path)
+            .map_err(|reason| LoadError::InvalidCartridge(path.to_path_buf(), reason))?;
+
+        platform = options.platform;
+        settings = Some(options);
+        (bytes, Format::OctoCartridge)
+    } else if contents.starts_with(HP48_HEADER) {
+        platform = Some(Platform::SuperChip);
+        (unwrap_hp48(path, &contents)?, Format::Hp48String)
+    } else if hex_extension || looks_like_hex(&contents) {
+        (parse_hex(path, &contents)?, Format::HexText)
+    } else {
+        (contents, Format::Binary)
+    };
+
+    if bytes.is_empty() {
+        return Err(LoadError::Empty(path.to_path_buf()));
+    }
+
+    let too_large = |limit, platform| LoadError::TooLarge { path: path.to_path_buf(), size: bytes.len(), limit, platform };
+
+    if let Some(platform) = platform.filter(|platform| bytes.len() > platform.program_space()) {
+        return Err(too_large(platform.program_space(), Some(platform)));
+    }
+
+    let free = memory_size.saturating_sub(load_address as usize);
+    if bytes.len() > free {
+        return Err(too_large(free, None));
+    }
+
+    Ok(Rom { bytes, format, platform, settings })
+}
+
+/// A warning for ROMs which fit in memory but are larger than `platform` allows.
+pub fn size_warning(rom: &[u8], platform: Platform) -> Option<String> {
+    if rom.len() <= platform.program_space() {
+        return None;
+    }
+
+    Some(format!(
+        "the ROM is {} bytes, more than the {} bytes {} leaves for programs",
+        rom.len(), platform.program_space(), platform
+    ))
+}
+
+fn looks_like_hex(contents: &[u8]) -> bool {
+    let text = match std::str::from_utf8(contents) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+
+    text.chars().any(|c| c.is_ascii_hexdigit())
+        && text.chars().all(|c| c.is_ascii_hexdigit() || c.is_ascii_whitespace() || c == ',' || c == 'x' || c == 'X')
+}
+
+/// Parses whitespace or comma separated hex, with optional `0x` prefixes and `#` or `;` comments.
+fn parse_hex(path: &Path, contents: &[u8]) -> Result<Vec<u8>, LoadError> {
+    let text = String::from_utf8_lossy(contents);
+    let mut bytes = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split(&['#', ';'][..]).next().unwrap_or("");
+
+        for token in line.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty()) {
+            let invalid = || LoadError::InvalidHex {
+                path: path.to_path_buf(),
+                line: number + 1,
+                token: token.to_string(),
+            };
+
+            let digits = token.trim_start_matches("0x").trim_start_matches("0X");
+            if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+
+            for pair in digits.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).unwrap();
+                bytes.push(u8::from_str_radix(pair, 16).map_err(|_| invalid())?);
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Extracts the program from an HP48 string object, the way SCHIP programs were distributed.
+///
+/// After the `HPHP48-x` header comes the string prolog and a five nibble length, stored low
+/// nibble first, which counts itself along with the data.
+fn unwrap_hp48(path: &Path, contents: &[u8]) -> Result<Vec<u8>, LoadError> {
+    let invalid = |reason| LoadError::InvalidHp48(path.to_path_buf(), reason);
+
+    if contents.len() < HP48_DATA_OFFSET {
+        return Err(invalid("the file ends before the object does"));
+    }
+
+    if contents[8..10] != HP48_STRING_PROLOG || contents[10] & 0xF != 0x0 {
+        return Err(invalid("the object is not a string"));
+    }
+
+    let nibbles = [
+        contents[10] >> 4,
+        contents[11] & 0xF,
+        contents[11] >> 4,
+        contents[12] & 0xF,
+        contents[12] >> 4,
+    ];
+
+    let length = nibbles.iter().rev().fold(0usize, |length, nibble| (length << 4) | *nibble as usize);
+    let size = length.checked_sub(nibbles.len()).ok_or_else(|| invalid("the string length is invalid"))? / 2;
+
+    contents.get(HP48_DATA_OFFSET..HP48_DATA_OFFSET + size)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| invalid("the file is shorter than the string length says"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::memory::{MAX_MEMORY_SIZE, MEMORY_SIZE, PROGRAM_START};
+
+    fn load_bytes(name: &str, bytes: &[u8], memory_size: usize) -> Result<Rom, LoadError> {
+        load_bytes_at(name, bytes, memory_size, PROGRAM_START)
+    }
+
+    fn load_bytes_at(name: &str, bytes: &[u8], memory_size: usize, load_address: Address) -> Result<Rom, LoadError> {
+        let path = env::temp_dir().join(format!("alvin-loader-{}-{}", process::id(), name));
+        fs::write(&path, bytes).unwrap();
+
+        let rom = load(&path, memory_size, load_address);
+        fs::remove_file(&path).unwrap();
+        rom
+    }
+
+    #[test]
+    fn roms_are_held_to_the_memory_they_will_run_in() {
+        let rom = vec![0x12; MEMORY_SIZE];
+
+        assert!(load_bytes("small.ch8", &rom, MEMORY_SIZE).is_err());
+        assert_eq!(load_bytes("large.ch8", &rom, MAX_MEMORY_SIZE).ok().unwrap().bytes.len(), MEMORY_SIZE);
+        assert!(load_bytes("full.ch8", &rom[..MEMORY_SIZE - PROGRAM_START as usize], MEMORY_SIZE).is_ok());
+
+        // Loading higher up leaves less room
+        let full = &rom[..MEMORY_SIZE - 0x600];
+        assert!(load_bytes_at("high.ch8", full, MEMORY_SIZE, 0x600).is_ok());
+        match load_bytes_at("higher.ch8", full, MEMORY_SIZE, 0x602) {
+            Err(LoadError::TooLarge { limit, platform: None, .. }) => assert_eq!(limit, MEMORY_SIZE - 0x602),
+            _ => panic!("a ROM filling memory from 0x600 should not fit from 0x602"),
+        }
+    }
+
+    #[test]
+    fn file_formats_limit_roms_to_their_platform() {
+        let rom = vec![0x12; MEMORY_SIZE];
+
+        match load_bytes("large.sc8", &rom, MAX_MEMORY_SIZE) {
+            Err(LoadError::TooLarge { platform: Some(Platform::SuperChip), .. }) => {}
+            _ => panic!("a 4K .sc8 ROM should be too large for SCHIP"),
+        }
+        assert!(load_bytes("large.xo8", &rom, MAX_MEMORY_SIZE).is_ok());
+    }
+}
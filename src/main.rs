@@ -1,27 +1,62 @@
 #![feature(duration_as_u128)]
 
 use std::{
-    fs::File,
-    io::{
-        BufRead,
-        BufReader,
-    },
+    fs,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
 };
 
 use clap::{App, Arg, crate_version};
 
 use crate::{
+    analyzer::{analyze, print_report},
+    assembler::{assemble_file, parse_number, AssembleError, Program},
+    debug_map::DebugMap,
     disassembler::disassemble,
-    emulator::System,
+    display::MAX_SCALE,
+    emulator::{Address, System},
+    font::Font,
+    info::print_info,
+    loader::{size_warning, Format},
+    memory::{Layout, MAX_MEMORY_SIZE, PROGRAM_START},
+    octo::compile_file,
+    opcode::Syntax,
+    palette::{parse_color, Palette},
+    persistence::{Persistence, DEFAULT_HALF_LIFE},
+    recorder::{DEFAULT_RECORD_SCALE, MAX_RECORD_SCALE},
+    upscale::Upscaler,
+    platform::Platform,
+    rom_db::{sha1, RomDatabase, RomEntry},
 };
 
 mod input;
 mod sound;
 mod opcode;
 mod memory;
+mod font;
+mod framebuffer;
+mod palette;
+mod persistence;
+mod upscale;
+mod screenshot;
+mod recorder;
 mod display;
+mod osd;
 mod emulator;
 mod disassembler;
+mod assembler;
+mod octo;
+mod debug_map;
+mod cfg;
+mod platform;
+mod analyzer;
+mod rom_db;
+mod info;
+mod loader;
+mod cartridge;
+mod debugger;
+mod dap;
 
 fn main() {
     let matches = App::new("alvin")
@@ -32,9 +67,71 @@ fn main() {
             .value_name("FILE")
             .help("What file to load")
             .takes_value(true)
-            .required(true)
+            .required(false)
+            .global(true)
+        )
+        .arg(Arg::with_name("symbols")
+            .long("symbols")
+            .value_name("FILE")
+            .help("A symbol file naming addresses, with `name = 0x2a0` lines or Octo's exported labels")
+            .takes_value(true)
+            .required(false)
+            .global(true)
+        )
+        .arg(Arg::with_name("rom-db")
+            .long("rom-db")
+            .value_name("FILE")
+            .help("A ROM database to use on top of the builtin one and ~/.config/alvin/roms.json")
+            .takes_value(true)
+            .required(false)
+            .global(true)
+        )
+        .subcommand(clap::SubCommand::with_name("disassemble")
+            .arg(Arg::with_name("syntax")
+                .long("syntax")
+                .value_name("SYNTAX")
+                .help("Which assembly syntax to print")
+                .takes_value(true)
+                .possible_values(&["classic", "octo"])
+                .default_value("classic")
+            )
+            .arg(Arg::with_name("cfg")
+                .long("cfg")
+                .value_name("FILE")
+                .help("Write the control-flow graph to a Graphviz file instead of printing the disassembly")
+                .takes_value(true)
+                .required(false)
+            )
+        )
+        .subcommand(clap::SubCommand::with_name("analyze")
+            .about("Report the extended and quirk-sensitive instructions a ROM uses, and the platform it targets")
+        )
+        .subcommand(clap::SubCommand::with_name("info")
+            .about("Print a ROM's size, hash, platform, entry point, database title and opcodes")
+        )
+        .subcommand(clap::SubCommand::with_name("assemble")
+            .arg(Arg::with_name("input")
+                .value_name("INPUT")
+                .help("The assembly or Octo (.8o) source to assemble")
+                .required(true)
+                .index(1)
+            )
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Where to write the assembled program, defaults to INPUT with a .ch8 extension")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("map")
+                .long("map")
+                .value_name("FILE")
+                .help("Where to write the debug map, defaults to the output with a .map extension")
+                .takes_value(true)
+                .required(false)
+            )
         )
-        .subcommand(clap::SubCommand::with_name("disassemble"))
         .subcommand(clap::SubCommand::with_name("run")
             .arg(Arg::with_name("debug")
                 .long("debug")
@@ -42,22 +139,351 @@ fn main() {
                 .takes_value(false)
                 .required(false)
             )
+            .arg(Arg::with_name("map")
+                .long("map")
+                .value_name("FILE")
+                .help("A debug map to show source lines and symbols with, defaults to FILE with a .map extension")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("platform")
+                .long("platform")
+                .value_name("PLATFORM")
+                .help("Which platform's quirks to emulate, auto picks one by analyzing the ROM")
+                .takes_value(true)
+                .possible_values(&["auto", "chip8", "schip", "xochip"])
+                .default_value("auto")
+            )
+            .arg(Arg::with_name("scale")
+                .long("scale")
+                .value_name("N")
+                .help("How many times larger than 64x32 to open the window, which can be resized and made fullscreen with F11")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("persistence")
+                .long("persistence")
+                .value_name("FILTER")
+                .help("Reduce flicker by keeping pixels on screen after they're erased: or shows the last two frames, phosphor fades them out")
+                .takes_value(true)
+                .possible_values(&["off", "or", "phosphor"])
+                .default_value("off")
+            )
+            .arg(Arg::with_name("half-life")
+                .long("half-life")
+                .value_name("MS")
+                .help("How many milliseconds phosphor persistence takes to fade a pixel to half brightness")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("filter")
+                .long("filter")
+                .value_name("FILTER")
+                .help("How to smooth the screen as it's scaled up: nearest keeps square pixels, scale2x, scale3x and hq round off diagonals, crt draws scanlines")
+                .takes_value(true)
+                .possible_values(&["nearest", "scale2x", "scale3x", "hq", "crt"])
+                .default_value("nearest")
+            )
+            .arg(Arg::with_name("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Record every frame from the start, F9 starts and stops recordings: an animated GIF for .gif, raw RGB frames otherwise, or raw frames piped into a command given as `|command`")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("record-scale")
+                .long("record-scale")
+                .value_name("N")
+                .help("How many times larger than 128x64 recorded frames are, 4 by default")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("record-audio")
+                .long("record-audio")
+                .help("Record the beeper into a WAV file alongside each recording")
+            )
+            .arg(Arg::with_name("palette")
+                .long("palette")
+                .value_name("PALETTE")
+                .help("A theme (alvin, classic, amber, octo, high-contrast, colorblind) or comma separated #rrggbb colours: background, plane 1, plane 2 and both planes")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("font")
+                .long("font")
+                .value_name("FONT")
+                .help("Which interpreter's hex digit glyphs to use, defaults to the platform's")
+                .takes_value(true)
+                .possible_values(&["vip", "schip", "octo", "dream6800", "eti660"])
+                .conflicts_with("font-file")
+            )
+            .arg(Arg::with_name("font-file")
+                .long("font-file")
+                .value_name("FILE")
+                .help("A font to use, with 80 bytes of small glyphs optionally followed by up to 16 large ones")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("layout")
+                .long("layout")
+                .value_name("HOST")
+                .help("Which host's memory layout to use, defaults to the platform's")
+                .takes_value(true)
+                .possible_values(&["vip", "eti660", "schip", "xochip"])
+            )
+            .arg(Arg::with_name("load-address")
+                .long("load-address")
+                .value_name("ADDRESS")
+                .help("Where to load the program, also the entry point unless --entry is given")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("entry")
+                .long("entry")
+                .value_name("ADDRESS")
+                .help("Where to start executing")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("memory-size")
+                .long("memory-size")
+                .value_name("BYTES")
+                .help("How much memory the host has")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("reserve")
+                .long("reserve")
+                .value_name("START-END")
+                .help("A range of memory the interpreter keeps for itself, which programs can't be loaded into")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false)
+            )
+        )
+        .subcommand(clap::SubCommand::with_name("dap")
+            .about("Serve the Debug Adapter Protocol over stdin and stdout")
         )
         .get_matches();
 
-    let filename = matches.value_of("file").expect("file is required");
-    let file = File::open(filename).expect("file not found");
+    if let Some(assemble_matches) = matches.subcommand_matches("assemble") {
+        let input = Path::new(assemble_matches.value_of("input").unwrap());
+        let output = assemble_matches.value_of("output")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| input.with_extension("ch8"));
 
-    let mut reader = BufReader::new(&file);
-    let buffer = reader.fill_buf().unwrap();
+        let map = assemble_matches.value_of("map")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| output.with_extension("map"));
+
+        assemble(input, &output, &map);
+        return;
+    }
+
+    if matches.subcommand_name() == Some("dap") {
+        if let Err(error) = dap::serve() {
+            eprintln!("ERROR: debug adapter failed: {}", error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let subcommand_file = matches.subcommand().1.and_then(|subcommand| subcommand.value_of("file"));
+    let filename = match matches.value_of("file").or(subcommand_file) {
+        Some(filename) => filename,
+        None => {
+            println!("ERROR: a file must be provided with --file");
+            process::exit(1);
+        }
+    };
+    let (buffer, debug_map, format_platform, settings) = if is_octo_source(Path::new(filename)) {
+        let program = build(Path::new(filename));
+        (program.rom, Some(program.debug_map), None, None)
+    } else {
+        let run_matches = matches.subcommand_matches("run");
+        let memory_size = run_matches.map(memory_size).unwrap_or(MAX_MEMORY_SIZE);
+        let load_address = run_matches.map(load_address).unwrap_or(PROGRAM_START);
+        match loader::load(Path::new(filename), memory_size, load_address) {
+            Ok(rom) => {
+                match rom.format {
+                    Format::Binary => {}
+                    Format::HexText => println!("Read {} as a hex dump", filename),
+                    Format::Hp48String => println!("Unwrapped {} from an HP48 string", filename),
+                    Format::OctoCartridge => println!("Compiled {} from an Octo cartridge", filename),
+                }
+
+                (rom.bytes, None, rom.platform, rom.settings)
+            }
+            Err(error) => {
+                println!("ERROR: {}", error);
+                process::exit(1);
+            }
+        }
+    };
+    let buffer = buffer.as_slice();
+
+    let subcommand_rom_db = matches.subcommand().1.and_then(|subcommand| subcommand.value_of("rom-db"));
+    let rom_db = matches.value_of("rom-db").or(subcommand_rom_db);
+
+    let subcommand_symbols = matches.subcommand().1.and_then(|subcommand| subcommand.value_of("symbols"));
+    let symbols = matches.value_of("symbols").or(subcommand_symbols);
 
     match matches.subcommand_name() {
-        Some("disassemble") => disassemble(buffer),
+        Some("disassemble") => {
+            let disassemble_matches = matches.subcommand_matches("disassemble").unwrap();
+            let syntax = disassemble_matches
+                .value_of("syntax")
+                .and_then(Syntax::from_name)
+                .unwrap();
+
+            let mut debug_map = DebugMap::new();
+            if let Some(symbols) = symbols {
+                load_symbols(&mut debug_map, Path::new(symbols));
+            }
+
+            match disassemble_matches.value_of("cfg") {
+                Some(cfg) => {
+                    if let Err(error) = cfg::export(buffer, syntax, &debug_map, Path::new(cfg)) {
+                        println!("ERROR: could not write {}: {}", cfg, error);
+                        process::exit(1);
+                    }
+                }
+                None => disassemble(buffer, syntax, &debug_map),
+            }
+        }
+        Some("analyze") => {
+            print_report(&analyze(buffer));
+        }
+        Some("info") => {
+            let rom_db = load_rom_db(rom_db);
+            print_info(buffer, rom_db.lookup(buffer));
+        }
         Some("run") => {
-            let mut system = System::new(buffer);
+            let run_matches = matches.subcommand_matches("run").unwrap();
+
+            let mut debug_map = debug_map
+                .or_else(|| load_debug_map(Path::new(filename), run_matches.value_of("map")))
+                .unwrap_or_default();
+            if let Some(symbols) = symbols {
+                load_symbols(&mut debug_map, Path::new(symbols));
+            }
+
+            // Options carried by the ROM itself are more specific than the database's
+            let (entry, source) = match settings {
+                Some(settings) => (settings, "cartridge"),
+                None => (load_rom_db(rom_db).lookup(buffer).cloned().unwrap_or_default(), "database"),
+            };
+            describe_rom(buffer, &entry, source);
+
+            let (platform, quirks) = match run_matches.value_of("platform").and_then(Platform::from_name) {
+                Some(platform) => (platform, platform.quirks()),
+                None => {
+                    let platform = entry.platform.or(format_platform).unwrap_or_else(|| {
+                        let platform = analyze(buffer).platform;
+                        println!("Detected platform: {}", platform);
+                        platform
+                    });
+
+                    (platform, entry.quirks.unwrap_or_else(|| platform.quirks()))
+                }
+            };
+
+            let layout = layout(run_matches, platform);
+            if buffer.len() > layout.program_space() {
+                println!(
+                    "ERROR: the ROM is {} bytes, but only {} bytes fit from {:#05x}",
+                    buffer.len(), layout.program_space(), layout.load_address
+                );
+                process::exit(1);
+            }
+
+            if let Some(warning) = size_warning(buffer, platform) {
+                println!("WARNING: {}", warning);
+            }
+
+            let font = match run_matches.value_of("font-file") {
+                Some(path) => Font::load(Path::new(path)).unwrap_or_else(|error| {
+                    println!("ERROR: could not load font {}: {}", path, error);
+                    process::exit(1);
+                }),
+                None => run_matches.value_of("font")
+                    .and_then(Font::from_name)
+                    .unwrap_or_else(|| Font::for_platform(platform)),
+            };
+
+            let mut system = System::new(buffer, layout, font);
+            system.set_debug_map(debug_map);
+            if let Some(name) = Path::new(filename).file_stem() {
+                system.set_name(&name.to_string_lossy());
+            }
+            system.set_quirks(quirks);
+            apply_rom_entry(&mut system, &entry);
+
+            if let Some(scale) = run_matches.value_of("scale") {
+                match scale.parse() {
+                    Ok(scale) if (1..=MAX_SCALE).contains(&scale) => system.set_scale(scale),
+                    _ => {
+                        println!("ERROR: `{}` is not a valid --scale, expected a whole number from 1 to {}", scale, MAX_SCALE);
+                        process::exit(1);
+                    }
+                }
+            }
+
+            let half_life = match run_matches.value_of("half-life") {
+                Some(half_life) => match half_life.parse() {
+                    Ok(milliseconds) => Duration::from_millis(milliseconds),
+                    Err(_) => {
+                        println!("ERROR: `{}` is not a valid --half-life, expected milliseconds", half_life);
+                        process::exit(1);
+                    }
+                },
+                None => DEFAULT_HALF_LIFE,
+            };
+
+            let persistence = run_matches.value_of("persistence")
+                .and_then(|persistence| Persistence::from_name(persistence, half_life))
+                .unwrap();
+            system.set_persistence(persistence);
 
-            let dump_state = matches.subcommand_matches("run").unwrap()
-                .is_present("debug");
+            let upscaler = run_matches.value_of("filter")
+                .and_then(Upscaler::from_name)
+                .unwrap();
+            system.set_upscaler(upscaler);
+
+            if let Some(palette) = run_matches.value_of("palette") {
+                match Palette::parse(palette) {
+                    Some(palette) => system.set_palette(palette),
+                    None => {
+                        println!("ERROR: `{}` is neither a theme nor two to four #rrggbb colours", palette);
+                        process::exit(1);
+                    }
+                }
+            }
+
+            let record_scale = match run_matches.value_of("record-scale") {
+                Some(scale) => match scale.parse() {
+                    Ok(scale) if (1..=MAX_RECORD_SCALE).contains(&scale) => scale,
+                    _ => {
+                        println!(
+                            "ERROR: `{}` is not a valid --record-scale, expected a whole number from 1 to {}",
+                            scale, MAX_RECORD_SCALE
+                        );
+                        process::exit(1);
+                    }
+                },
+                None => DEFAULT_RECORD_SCALE,
+            };
+            system.set_recording(run_matches.is_present("record-audio"), record_scale);
+
+            if let Some(target) = run_matches.value_of("record") {
+                if let Err(error) = system.start_recording(target) {
+                    println!("ERROR: {}", error);
+                    process::exit(1);
+                }
+            }
+
+            let dump_state = run_matches.is_present("debug");
             system.run(dump_state);
         }
         _ => {
@@ -65,3 +491,196 @@ fn main() {
         }
     }
 }
+
+fn is_octo_source(path: &Path) -> bool {
+    path.extension().map(|extension| extension == "8o").unwrap_or(false)
+}
+
+/// Assembles or compiles `input` depending on its extension, exiting with the errors on failure.
+fn build(input: &Path) -> Program {
+    let result: Result<Program, Vec<AssembleError>> = if is_octo_source(input) {
+        compile_file(input)
+    } else {
+        assemble_file(input)
+    };
+
+    match result {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors.iter() {
+                eprintln!("{}", error);
+            }
+
+            process::exit(1);
+        }
+    }
+}
+
+fn assemble(input: &Path, output: &Path, map: &Path) {
+    let program = build(input);
+
+    if let Err(error) = fs::write(output, program.rom) {
+        eprintln!("ERROR: could not write {}: {}", output.display(), error);
+        process::exit(1);
+    }
+
+    if let Err(error) = program.debug_map.save(map) {
+        eprintln!("ERROR: could not write {}: {}", map.display(), error);
+        process::exit(1);
+    }
+}
+
+/// Loads the debug map given on the command line, or one sitting next to the ROM.
+fn load_debug_map(rom: &Path, map: Option<&str>) -> Option<DebugMap> {
+    let (path, explicit) = match map {
+        Some(map) => (PathBuf::from(map), true),
+        None => (rom.with_extension("map"), false),
+    };
+
+    if !explicit && !path.exists() {
+        return None;
+    }
+
+    match DebugMap::load(&path) {
+        Ok(debug_map) => Some(debug_map),
+        Err(error) => {
+            println!("WARNING: could not load debug map {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+fn load_symbols(debug_map: &mut DebugMap, path: &Path) {
+    if let Err(error) = debug_map.load_symbols(path) {
+        println!("ERROR: could not load symbols {}: {}", path.display(), error);
+        process::exit(1);
+    }
+}
+
+/// The memory size `--memory-size` or `--layout` ask for, before the platform is known. Without
+/// either the platform decides, so ROMs are only held to the largest memory any platform has.
+fn memory_size(matches: &clap::ArgMatches) -> usize {
+    matches.value_of("memory-size")
+        .and_then(parse_number)
+        .filter(|size| (0..=MAX_MEMORY_SIZE as i64).contains(size))
+        .map(|size| size as usize)
+        .or_else(|| matches.value_of("layout").and_then(Layout::from_name).map(|layout| layout.size))
+        .unwrap_or(MAX_MEMORY_SIZE)
+}
+
+/// Where `--load-address` or `--layout` put the program, before the platform is known. Every
+/// platform loads programs at the same address otherwise.
+fn load_address(matches: &clap::ArgMatches) -> Address {
+    matches.value_of("load-address")
+        .and_then(parse_number)
+        .filter(|address| (0..MAX_MEMORY_SIZE as i64).contains(address))
+        .map(|address| address as Address)
+        .or_else(|| matches.value_of("layout").and_then(Layout::from_name).map(|layout| layout.load_address))
+        .unwrap_or(PROGRAM_START)
+}
+
+/// The memory layout for `platform`, or the host named with `--layout`, adjusted by the other layout arguments.
+fn layout(matches: &clap::ArgMatches, platform: Platform) -> Layout {
+    let mut layout = matches.value_of("layout")
+        .and_then(Layout::from_name)
+        .unwrap_or_else(|| Layout::for_platform(platform));
+
+    let number = |name: &str| matches.value_of(name).map(|value| {
+        parse_number(value).filter(|number| (0..=MAX_MEMORY_SIZE as i64).contains(number)).unwrap_or_else(|| {
+            println!("ERROR: `{}` is not a valid --{}", value, name);
+            process::exit(1);
+        }) as usize
+    });
+
+    if let Some(size) = number("memory-size") {
+        layout.size = size;
+        layout.reserved.retain(|(start, _)| *start < size);
+    }
+
+    if let Some(address) = number("load-address") {
+        layout.load_address = address as u16;
+        layout.entry_point = address as u16;
+    }
+
+    if let Some(address) = number("entry") {
+        layout.entry_point = address as u16;
+    }
+
+    for range in matches.values_of("reserve").into_iter().flatten() {
+        let bounds: Vec<_> = range.splitn(2, '-').map(parse_number).collect();
+        match bounds.as_slice() {
+            [Some(start), Some(end)] if 0 <= *start && start < end => layout.reserved.push((*start as usize, *end as usize)),
+            _ => {
+                println!("ERROR: `{}` is not a valid --reserve range, expected START-END", range);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Err(error) = layout.check() {
+        println!("ERROR: invalid memory layout: {}", error);
+        process::exit(1);
+    }
+
+    layout
+}
+
+fn load_rom_db(extra: Option<&str>) -> RomDatabase {
+    match RomDatabase::load(extra.map(Path::new)) {
+        Ok(rom_db) => rom_db,
+        Err(error) => {
+            println!("ERROR: could not load ROM database: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn describe_rom(rom: &[u8], entry: &RomEntry, source: &str) {
+    match (&entry.title, &entry.author) {
+        (Some(title), Some(author)) => println!("ROM: {} by {}", title, author),
+        (Some(title), None) => println!("ROM: {}", title),
+        _ => println!("ROM: not in the {} (SHA-1 {})", source, sha1(rom)),
+    }
+
+    if let Some(platform) = entry.platform {
+        println!("  platform: {}", platform);
+    }
+
+    if entry.quirks.is_some() {
+        println!("  quirks: from the {}", source);
+    }
+
+    if let Some(speed) = entry.speed {
+        println!("  speed: {} instructions per frame", speed);
+    }
+
+    for (key, keyboard) in entry.keys.iter() {
+        println!("  key {:X}: {}", key, keyboard);
+    }
+
+    if !entry.palette.is_empty() {
+        println!("  palette: {}", entry.palette.join(" "));
+    }
+}
+
+fn apply_rom_entry(system: &mut System, entry: &RomEntry) {
+    if let Some(speed) = entry.speed {
+        system.set_speed(speed);
+    }
+
+    for (key, keyboard) in entry.keys.iter() {
+        if !system.map_key(*key, keyboard) {
+            println!("WARNING: unknown key `{}` for {:X}", keyboard, key);
+        }
+    }
+
+    let colors: Vec<_> = entry.palette.iter().filter_map(|color| parse_color(color)).collect();
+    if colors.is_empty() {
+        return;
+    }
+
+    match Palette::from_colors(&colors) {
+        Some(palette) => system.set_palette(palette),
+        None => println!("WARNING: a palette needs a background and a foreground colour, and at most four colours"),
+    }
+}
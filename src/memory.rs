@@ -1,37 +1,98 @@
+use crate::{
+    emulator::Address,
+    font::Font,
+    platform::Platform,
+};
+
+pub const MEMORY_SIZE: usize = 0x1000;
+pub const PROGRAM_START: Address = 0x200;
+/// The COSMAC VIP interpreter keeps its stack and display buffer from here up.
+pub const PROGRAM_END: Address = 0xEA0;
+/// XO-CHIP's 16 bit `F000 NNNN` can address a full 64K.
+pub const MAX_MEMORY_SIZE: usize = 0x10000;
+/// The ETI-660 interpreter takes the memory below here for itself.
+pub const ETI660_PROGRAM_START: Address = 0x600;
+
+/// Where a host loads and starts programs, and which parts of its memory are off limits to them.
+#[derive(Clone)]
+pub struct Layout {
+    pub load_address: Address,
+    pub entry_point: Address,
+    pub size: usize,
+    /// Ranges, end exclusive, the interpreter keeps for its stack, display buffer and variables.
+    pub reserved: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "vip" => Some(Layout::for_platform(Platform::Chip8)),
+            "eti660" => Some(Layout {
+                load_address: ETI660_PROGRAM_START,
+                entry_point: ETI660_PROGRAM_START,
+                ..Layout::for_platform(Platform::Chip8)
+            }),
+            "schip" => Some(Layout::for_platform(Platform::SuperChip)),
+            "xochip" => Some(Layout::for_platform(Platform::XoChip)),
+            _ => None,
+        }
+    }
+
+    pub fn for_platform(platform: Platform) -> Layout {
+        let (size, reserved) = match platform {
+            Platform::Chip8 => (MEMORY_SIZE, vec![(PROGRAM_END as usize, MEMORY_SIZE)]),
+            Platform::SuperChip => (MEMORY_SIZE, vec![]),
+            Platform::XoChip => (MAX_MEMORY_SIZE, vec![]),
+        };
+
+        Layout {
+            load_address: PROGRAM_START,
+            entry_point: PROGRAM_START,
+            size,
+            reserved,
+        }
+    }
+
+    /// How many bytes fit between the load address and the next reserved range or the end of memory.
+    pub fn program_space(&self) -> usize {
+        let start = self.load_address as usize;
+        let end = self.reserved.iter()
+            .map(|(reserved_start, _)| *reserved_start)
+            .filter(|reserved_start| *reserved_start > start)
+            .fold(self.size, usize::min);
+
+        end.saturating_sub(start)
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.size < FONT_END || self.size > MAX_MEMORY_SIZE {
+            return Err(format!("memory must be between {} and {} bytes", FONT_END, MAX_MEMORY_SIZE));
+        }
+
+        if self.load_address as usize >= self.size || self.entry_point as usize >= self.size {
+            return Err(format!("the load address and entry point must be below {:#x}", self.size));
+        }
+
+        if (self.load_address as usize) < FONT_END {
+            return Err(format!("programs can't be loaded over the fonts below {:#x}", FONT_END));
+        }
+
+        let load_address = self.load_address as usize;
+        if let Some((start, end)) = self.reserved.iter().find(|(start, end)| *start <= load_address && load_address < *end) {
+            return Err(format!("the load address is inside the reserved range {:#x}-{:#x}", start, end));
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the 6-bit ASCII characters start, after the fonts `font` places below.
+pub const ASCII_ADDRESS: usize = 0xF0;
+/// Programs can't be loaded below here without overwriting the fonts.
+pub const FONT_END: usize = ASCII_ADDRESS + ASCII_DATA.len();
+
 // Sprite data borrowed from https://github.com/massung/CHIP-8/blob/master/chip8/rom.go
-const SPRITE_DATA: [u8; 0x1C0] = [
-    // 4x5 low-res mode font sprites (0-F)
-    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20,
-    0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
-    0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20,
-    0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
-    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40,
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
-    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0,
-    0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
-    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80,
-    0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
-    // 8x10 high-res mode font sprites (0-F)
-    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7,
-    0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18,
-    0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06,
-    0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E,
-    0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,
-    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF,
-    0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE,
-    0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xC0, 0xC0,
-    0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF,
-    0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
-    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3,
-    0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F,
-    0x03, 0x03, 0x3E, 0x7C, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+const ASCII_DATA: [u8; 0xD0] = [
     // 6-bit ASCII character patterns
     0x00, // |        |
     0x10, // |   *    |
@@ -117,22 +178,37 @@ const SPRITE_DATA: [u8; 0x1C0] = [
     0x88, 0x1F, 0x4F, // ?
 ];
 
-pub fn load_fonts(memory: &mut [u8]) {
-    let mut i = 0x0;
-    for sprite in SPRITE_DATA.iter() {
-        memory[i] = *sprite;
-        i += 1;
+pub const GLYPH_HEIGHT: usize = 5;
+const ASCII_PATTERNS: usize = 0x10;
+
+/// Decodes the rows, top first, and width of a character in the 6-bit ASCII set.
+///
+/// Each character is three bytes of nibbles, read low nibble first: five indexes into the row
+/// patterns from the bottom row up, then the character's width.
+pub fn ascii_glyph(character: char) -> Option<([u8; GLYPH_HEIGHT], usize)> {
+    if !(' '..='_').contains(&character) {
+        return None;
     }
-}
 
-pub fn load_program(memory: &mut [u8], program: &[u8]) {
-    let mut current_address = 0x200;
-    for byte in program.iter() {
-        if current_address == 0xEA0 {
-            break;
-        }
+    let offset = ASCII_PATTERNS + (character as usize & 0x3F) * 3;
+    let encoded = &ASCII_DATA[offset..offset + 3];
+    let nibble = |index: usize| (encoded[index / 2] >> (4 * (index % 2))) & 0xF;
 
-        memory[current_address] = *byte;
-        current_address += 1;
+    let mut rows = [0; GLYPH_HEIGHT];
+    for (index, row) in rows.iter_mut().rev().enumerate() {
+        *row = ASCII_DATA[nibble(index) as usize];
     }
-}
\ No newline at end of file
+
+    Some((rows, nibble(GLYPH_HEIGHT) as usize))
+}
+
+pub fn load_fonts(memory: &mut [u8], font: &Font) {
+    font.load_into(memory);
+    memory[ASCII_ADDRESS..FONT_END].copy_from_slice(&ASCII_DATA);
+}
+
+/// Copies the program into memory at the layout's load address, having already checked that it fits.
+pub fn load_program(memory: &mut [u8], program: &[u8], layout: &Layout) {
+    let start = layout.load_address as usize;
+    memory[start..start + program.len()].copy_from_slice(program);
+}
@@ -0,0 +1,768 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    assembler::{parse_number, parse_register, AssembleError, Backend, BinaryOp, Expr, Field, Location, Program, UnaryOp},
+    emulator::{Address, Register},
+    memory::PROGRAM_START,
+    opcode::Opcode,
+};
+
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    location: Location,
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Loop {
+    start: Address,
+    exits: Vec<Address>,
+    location: Location,
+}
+
+struct Branch {
+    jump: Address,
+    location: Location,
+}
+
+enum Test {
+    Equal,
+    NotEqual,
+    Key,
+    NoKey,
+}
+
+enum Operand {
+    Register(Register),
+    Value(Expr),
+}
+
+struct Condition {
+    register: Register,
+    test: Test,
+    operand: Option<Operand>,
+}
+
+/// Compiles Octo (`.8o`) source into a ROM.
+///
+/// Only the structured subset of the language is supported: labels, `loop`/`again`/`while`,
+/// `if ... then` and `if ... begin ... else ... end`, `:alias`, `:const`, `:macro` and `:calc`.
+struct Compiler {
+    backend: Backend,
+    tokens: VecDeque<Token>,
+    labels: HashSet<String>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, Register>,
+    macros: HashMap<String, Macro>,
+    loops: Vec<Loop>,
+    branches: Vec<Branch>,
+    started: bool,
+    expansions: usize,
+    end: Location,
+}
+
+pub fn compile_file(path: &Path) -> Result<Program, Vec<AssembleError>> {
+    let file: Rc<str> = Rc::from(path.display().to_string());
+    match fs::read_to_string(path) {
+        Ok(source) => compile(&source, file),
+        Err(error) => {
+            let location = Location::new(file, 0, 0);
+            Err(vec![AssembleError::new(&location, format!("could not read {}: {}", path.display(), error))])
+        }
+    }
+}
+
+pub fn compile(source: &str, file: Rc<str>) -> Result<Program, Vec<AssembleError>> {
+    let line_count = source.lines().count();
+    let mut compiler = Compiler {
+        backend: Backend::new(PROGRAM_START),
+        tokens: tokenize(source, &file),
+        labels: HashSet::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        loops: vec![],
+        branches: vec![],
+        started: false,
+        expansions: 0,
+        end: Location::new(file, line_count.max(1), 1),
+    };
+
+    if let Err(error) = compiler.run() {
+        compiler.backend.error(error);
+    }
+
+    compiler.backend.finish()
+}
+
+fn tokenize(source: &str, file: &Rc<str>) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        let mut start = None;
+        for (column, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(column),
+                (true, Some(first)) => {
+                    tokens.push_back(Token {
+                        text: line[first..column].to_string(),
+                        location: Location::new(file.clone(), number + 1, first + 1),
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    tokens
+}
+
+impl Compiler {
+    fn run(&mut self) -> Result<(), AssembleError> {
+        while let Some(token) = self.tokens.pop_front() {
+            self.backend.locate(&token.location);
+            self.statement(token)?;
+        }
+
+        if let Some(open) = self.loops.last() {
+            return Err(AssembleError::new(&open.location, "`loop` is never closed with `again`"));
+        }
+
+        if let Some(open) = self.branches.last() {
+            return Err(AssembleError::new(&open.location, "`begin` is never closed with `end`"));
+        }
+
+        if !self.labels.contains("main") {
+            return Err(AssembleError::new(&self.end, "the program has no `main` label"));
+        }
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Token, AssembleError> {
+        self.tokens.pop_front().ok_or_else(|| AssembleError::new(&self.end, "unexpected end of file"))
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AssembleError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(AssembleError::new(&token.location, format!("expected `{}` but found `{}`", text, token.text)));
+        }
+
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().map(|token| token.text == text).unwrap_or(false)
+    }
+
+    /// Octo starts execution at `main`, so unless it is the very first thing in the program
+    /// a jump to it has to be placed at the start of the ROM.
+    fn prologue(&mut self) {
+        if self.started {
+            return;
+        }
+
+        self.started = true;
+        if !self.labels.contains("main") {
+            let location = self.backend.location().clone();
+            self.backend.emit_opcode_with(Opcode::Goto(0), Field::Address, Expr::Symbol("main".to_string(), location));
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssembleError> {
+        let location = token.location.clone();
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                if name.text != "main" {
+                    self.prologue();
+                }
+
+                self.labels.insert(name.text.clone());
+                self.backend.define_label(&name.text);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = if self.peek_is("{") {
+                    let value = self.calc()?;
+                    if !(0..=0xF).contains(&value) {
+                        return Err(AssembleError::new(&name.location, format!("{} is not a register", value)));
+                    }
+
+                    value as Register
+                } else {
+                    self.register()?
+                };
+
+                self.backend.define_alias(&name.text, register);
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.constant()?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => {
+                let name = self.name()?;
+
+                let mut arguments = vec![];
+                while !self.peek_is("{") {
+                    arguments.push(self.name()?.text);
+                }
+
+                let body = self.block()?;
+                self.macros.insert(name.text, Macro { arguments, body });
+            }
+            ":org" => {
+                let address = self.constant()?;
+                self.backend.set_here(address);
+            }
+            ":breakpoint" => {
+                self.name()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            name if self.macros.contains_key(name) => self.expand(name, &location)?,
+            _ => {
+                self.prologue();
+                self.instruction(token)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instruction(&mut self, token: Token) -> Result<(), AssembleError> {
+        let location = token.location.clone();
+
+        match token.text.as_str() {
+            ":byte" => {
+                let value = if self.peek_is("{") { Expr::Number(self.calc()?) } else { self.value()? };
+                self.backend.emit_expression(Field::Byte, value);
+            }
+            ":call" => {
+                let target = self.value()?;
+                self.backend.emit_opcode_with(Opcode::CallFunction(0), Field::Address, target);
+            }
+            "clear" => self.backend.emit_opcode(Opcode::Clear),
+            "lores" => self.backend.emit_opcode(Opcode::LowResolution),
+            "hires" => self.backend.emit_opcode(Opcode::HighResolution),
+            "scroll-down" => {
+                let rows = self.value()?;
+                self.backend.emit_opcode_with(Opcode::ScrollDown(0), Field::Nibble, rows);
+            }
+            "scroll-up" => {
+                let rows = self.value()?;
+                self.backend.emit_opcode_with(Opcode::ScrollUp(0), Field::Nibble, rows);
+            }
+            "scroll-right" => self.backend.emit_opcode(Opcode::ScrollRight),
+            "scroll-left" => self.backend.emit_opcode(Opcode::ScrollLeft),
+            "plane" => {
+                let planes = self.value()?;
+                self.backend.emit_opcode_with(Opcode::SelectPlanes(0), Field::Planes, planes);
+            }
+            "return" | ";" => self.backend.emit_opcode(Opcode::Return),
+            "bcd" => {
+                let register = self.register()?;
+                self.backend.emit_opcode(Opcode::BinaryCodedDecimal(register));
+            }
+            "save" => {
+                let register = self.register()?;
+                self.backend.emit_opcode(Opcode::Dump(register));
+            }
+            "load" => {
+                let register = self.register()?;
+                self.backend.emit_opcode(Opcode::Load(register));
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.value()?;
+                self.backend.emit_opcode_with(Opcode::Draw(x, y, 0), Field::Nibble, height);
+            }
+            "jump" => {
+                let target = self.value()?;
+                self.backend.emit_opcode_with(Opcode::Goto(0), Field::Address, target);
+            }
+            "jump0" => {
+                let target = self.value()?;
+                self.backend.emit_opcode_with(Opcode::JumpOffset(0), Field::Address, target);
+            }
+            "native" => {
+                let target = self.value()?;
+                self.backend.emit_opcode_with(Opcode::Call(0), Field::Address, target);
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let register = self.register()?;
+                if token.text == "delay" {
+                    self.backend.emit_opcode(Opcode::SetDelayTimer(register));
+                } else {
+                    self.backend.emit_opcode(Opcode::SetSoundTimer(register));
+                }
+            }
+            "i" => {
+                let operator = self.next()?;
+                match operator.text.as_str() {
+                    ":=" if self.peek_is("hex") => {
+                        self.next()?;
+                        let register = self.register()?;
+                        self.backend.emit_opcode(Opcode::StoreSpriteAddress(register));
+                    }
+                    ":=" if self.peek_is("bighex") => {
+                        self.next()?;
+                        let register = self.register()?;
+                        self.backend.emit_opcode(Opcode::StoreLargeSpriteAddress(register));
+                    }
+                    ":=" => {
+                        let target = self.value()?;
+                        self.backend.emit_opcode_with(Opcode::SetAddressReg(0), Field::Address, target);
+                    }
+                    "+=" => {
+                        let register = self.register()?;
+                        self.backend.emit_opcode(Opcode::IncrementAddressReg(register));
+                    }
+                    other => return Err(AssembleError::new(&operator.location, format!("`i {}` is not a valid operation", other))),
+                }
+            }
+            "loop" => {
+                self.loops.push(Loop {
+                    start: self.backend.here(),
+                    exits: vec![],
+                    location,
+                });
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_unless(condition, true);
+
+                let exit = self.jump_placeholder();
+                match self.loops.last_mut() {
+                    Some(open) => open.exits.push(exit),
+                    None => return Err(AssembleError::new(&location, "`while` is only allowed inside of a `loop`")),
+                }
+            }
+            "again" => {
+                let open = self.loops.pop().ok_or_else(|| AssembleError::new(&location, "`again` without a matching `loop`"))?;
+                self.backend.emit_opcode(Opcode::Goto(open.start));
+
+                let here = self.backend.here();
+                for exit in open.exits {
+                    self.backend.fixup(exit, Field::Address, Expr::Number(here as i64));
+                }
+            }
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text.as_str() {
+                    "then" => self.skip_unless(condition, false),
+                    "begin" => {
+                        self.skip_unless(condition, true);
+                        let jump = self.jump_placeholder();
+                        self.branches.push(Branch { jump, location });
+                    }
+                    other => return Err(AssembleError::new(&keyword.location, format!("expected `then` or `begin` but found `{}`", other))),
+                }
+            }
+            "else" => {
+                let open = self.branches.pop().ok_or_else(|| AssembleError::new(&location, "`else` without a matching `begin`"))?;
+
+                let jump = self.jump_placeholder();
+                let here = self.backend.here();
+                self.backend.fixup(open.jump, Field::Address, Expr::Number(here as i64));
+
+                self.branches.push(Branch { jump, location });
+            }
+            "end" => {
+                let open = self.branches.pop().ok_or_else(|| AssembleError::new(&location, "`end` without a matching `begin`"))?;
+
+                let here = self.backend.here();
+                self.backend.fixup(open.jump, Field::Address, Expr::Number(here as i64));
+            }
+            text if self.lookup_register(text).is_some() => {
+                let register = self.lookup_register(text).unwrap();
+                self.assignment(register)?;
+            }
+            text => {
+                if let Some(value) = self.number(text) {
+                    if !(-0x80..=0xFF).contains(&value) {
+                        return Err(AssembleError::new(&location, format!("{} does not fit in a byte", value)));
+                    }
+
+                    self.backend.emit_byte(value as u8);
+                } else if is_keyword(text) || text.starts_with(':') {
+                    return Err(AssembleError::new(&location, format!("unexpected `{}`", text)));
+                } else {
+                    let target = Expr::Symbol(text.to_string(), location);
+                    self.backend.emit_opcode_with(Opcode::CallFunction(0), Field::Address, target);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn assignment(&mut self, register: Register) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+
+        match operator.text.as_str() {
+            ":=" if self.peek_is("random") => {
+                self.next()?;
+                let mask = self.value()?;
+                self.backend.emit_opcode_with(Opcode::SetRand(register, 0), Field::Byte, mask);
+            }
+            ":=" if self.peek_is("key") => {
+                self.next()?;
+                self.backend.emit_opcode(Opcode::StoreKeypress(register));
+            }
+            ":=" if self.peek_is("delay") => {
+                self.next()?;
+                self.backend.emit_opcode(Opcode::StoreDelayTimer(register));
+            }
+            ":=" => match self.operand()? {
+                Operand::Register(other) => self.backend.emit_opcode(Opcode::Copy(register, other)),
+                Operand::Value(value) => self.backend.emit_opcode_with(Opcode::Set(register, 0), Field::Byte, value),
+            },
+            "+=" => match self.operand()? {
+                Operand::Register(other) => self.backend.emit_opcode(Opcode::AddAssignReg(register, other)),
+                Operand::Value(value) => self.backend.emit_opcode_with(Opcode::AddAssign(register, 0), Field::Byte, value),
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(other) => self.backend.emit_opcode(Opcode::SubAssignReg(register, other)),
+                Operand::Value(value) => {
+                    let negated = Expr::Binary(BinaryOp::And, Box::new(Expr::Unary(UnaryOp::Negate, Box::new(value))), Box::new(Expr::Number(0xFF)));
+                    self.backend.emit_opcode_with(Opcode::AddAssign(register, 0), Field::Byte, negated);
+                }
+            },
+            "=-" => {
+                let other = self.register()?;
+                self.backend.emit_opcode(Opcode::Subtract(register, other));
+            }
+            "|=" => {
+                let other = self.register()?;
+                self.backend.emit_opcode(Opcode::Or(register, other));
+            }
+            "&=" => {
+                let other = self.register()?;
+                self.backend.emit_opcode(Opcode::And(register, other));
+            }
+            "^=" => {
+                let other = self.register()?;
+                self.backend.emit_opcode(Opcode::Xor(register, other));
+            }
+            ">>=" => {
+                let other = self.register()?;
+                self.backend.emit_opcode(Opcode::ShiftRight(register, other));
+            }
+            "<<=" => {
+                let other = self.register()?;
+                self.backend.emit_opcode(Opcode::ShiftLeft(register, other));
+            }
+            other => return Err(AssembleError::new(&operator.location, format!("`{}` is not a valid register operation", other))),
+        }
+
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let register = self.register()?;
+        let test = self.next()?;
+
+        let (test, operand) = match test.text.as_str() {
+            "==" => (Test::Equal, Some(self.operand()?)),
+            "!=" => (Test::NotEqual, Some(self.operand()?)),
+            "key" => (Test::Key, None),
+            "-key" => (Test::NoKey, None),
+            other => return Err(AssembleError::new(&test.location, format!("`{}` is not a supported comparison", other))),
+        };
+
+        Ok(Condition { register, test, operand })
+    }
+
+    /// Emits the instruction which skips over the next one when `condition` does not hold,
+    /// or when it does if `negate` is set.
+    fn skip_unless(&mut self, condition: Condition, negate: bool) {
+        let register = condition.register;
+        let test = match (condition.test, negate) {
+            (Test::Equal, false) | (Test::NotEqual, true) => Test::Equal,
+            (Test::NotEqual, false) | (Test::Equal, true) => Test::NotEqual,
+            (Test::Key, false) | (Test::NoKey, true) => Test::Key,
+            (Test::NoKey, false) | (Test::Key, true) => Test::NoKey,
+        };
+
+        match (test, condition.operand) {
+            (Test::Equal, Some(Operand::Register(other))) => self.backend.emit_opcode(Opcode::SkipNEqReg(register, other)),
+            (Test::NotEqual, Some(Operand::Register(other))) => self.backend.emit_opcode(Opcode::SkipEqReg(register, other)),
+            (Test::Equal, Some(Operand::Value(value))) => self.backend.emit_opcode_with(Opcode::SkipNEq(register, 0), Field::Byte, value),
+            (Test::NotEqual, Some(Operand::Value(value))) => self.backend.emit_opcode_with(Opcode::SkipEq(register, 0), Field::Byte, value),
+            (Test::Key, _) => self.backend.emit_opcode(Opcode::SkipNoKeyPress(register)),
+            (Test::NoKey, _) => self.backend.emit_opcode(Opcode::SkipKeyPress(register)),
+            (_, None) => unreachable!("comparisons always carry an operand"),
+        }
+    }
+
+    fn jump_placeholder(&mut self) -> Address {
+        let address = self.backend.here();
+        self.backend.emit_opcode(Opcode::Goto(0));
+        address
+    }
+
+    fn expand(&mut self, name: &str, location: &Location) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(AssembleError::new(location, format!("too many macro expansions while expanding `{}`", name)));
+        }
+
+        let argument_count = self.macros[name].arguments.len();
+        let mut values = HashMap::new();
+        for i in 0..argument_count {
+            let value = self.next()?;
+            values.insert(self.macros[name].arguments[i].clone(), value);
+        }
+
+        let body: Vec<Token> = self.macros[name].body.iter()
+            .map(|token| values.get(&token.text).cloned().unwrap_or_else(|| token.clone()))
+            .collect();
+
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self) -> Result<Vec<Token>, AssembleError> {
+        let open = self.expect("{")?;
+
+        let mut depth = 1;
+        let mut body = vec![];
+        loop {
+            let token = self.tokens.pop_front()
+                .ok_or_else(|| AssembleError::new(&open.location, "`{` is never closed"))?;
+
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+
+            if depth == 0 {
+                return Ok(body);
+            }
+
+            body.push(token);
+        }
+    }
+
+    fn name(&mut self) -> Result<Token, AssembleError> {
+        let token = self.next()?;
+        let valid = token.text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            && !token.text.starts_with(|c: char| c.is_ascii_digit() || c == '-');
+
+        if !valid || is_keyword(&token.text) || parse_register(&token.text).is_some() {
+            return Err(AssembleError::new(&token.location, format!("`{}` is not a valid name", token.text)));
+        }
+
+        Ok(token)
+    }
+
+    fn lookup_register(&self, text: &str) -> Option<Register> {
+        parse_register(text).or_else(|| self.aliases.get(text).cloned())
+    }
+
+    fn register(&mut self) -> Result<Register, AssembleError> {
+        let token = self.next()?;
+        self.lookup_register(&token.text)
+            .ok_or_else(|| AssembleError::new(&token.location, format!("expected a register but found `{}`", token.text)))
+    }
+
+    fn number(&self, text: &str) -> Option<i64> {
+        match text.strip_prefix('-') {
+            Some(magnitude) => parse_number(magnitude).map(|value| -value),
+            None if text.starts_with(|c: char| c.is_ascii_digit()) => parse_number(text),
+            None => self.constants.get(text).cloned(),
+        }
+    }
+
+    fn constant(&mut self) -> Result<i64, AssembleError> {
+        let token = self.next()?;
+        self.number(&token.text)
+            .ok_or_else(|| AssembleError::new(&token.location, format!("expected a constant but found `{}`", token.text)))
+    }
+
+    fn value(&mut self) -> Result<Expr, AssembleError> {
+        let token = self.next()?;
+        if let Some(value) = self.number(&token.text) {
+            return Ok(Expr::Number(value));
+        }
+
+        if is_keyword(&token.text) || self.lookup_register(&token.text).is_some() {
+            return Err(AssembleError::new(&token.location, format!("expected a value but found `{}`", token.text)));
+        }
+
+        Ok(Expr::Symbol(token.text, token.location))
+    }
+
+    fn operand(&mut self) -> Result<Operand, AssembleError> {
+        let register = self.tokens.front().and_then(|token| self.lookup_register(&token.text));
+        match register {
+            Some(register) => {
+                self.next()?;
+                Ok(Operand::Register(register))
+            }
+            None => self.value().map(Operand::Value),
+        }
+    }
+
+    /// Evaluates a `{ ... }` expression at compile time.
+    ///
+    /// Like Octo, operators have no precedence and are applied right to left.
+    fn calc(&mut self) -> Result<i64, AssembleError> {
+        let open = self.tokens.front().map(|token| token.location.clone()).unwrap_or_else(|| self.end.clone());
+        let body = self.block()?;
+
+        let mut position = 0;
+        let expr = self.calc_expression(&body, &mut position, &open)?;
+        if let Some(extra) = body.get(position) {
+            return Err(AssembleError::new(&extra.location, format!("unexpected `{}`", extra.text)));
+        }
+
+        self.backend.evaluate(&expr, &open)
+    }
+
+    fn calc_expression(&self, tokens: &[Token], position: &mut usize, open: &Location) -> Result<Expr, AssembleError> {
+        let left = self.calc_term(tokens, position, open)?;
+
+        let op = match tokens.get(*position).map(|token| token.text.as_str()) {
+            Some("+") => BinaryOp::Add,
+            Some("-") => BinaryOp::Subtract,
+            Some("*") => BinaryOp::Multiply,
+            Some("/") => BinaryOp::Divide,
+            Some("%") => BinaryOp::Modulo,
+            Some("&") => BinaryOp::And,
+            Some("|") => BinaryOp::Or,
+            Some("^") => BinaryOp::Xor,
+            Some("<<") => BinaryOp::ShiftLeft,
+            Some(">>") => BinaryOp::ShiftRight,
+            _ => return Ok(left),
+        };
+
+        *position += 1;
+        let right = self.calc_expression(tokens, position, open)?;
+
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn calc_term(&self, tokens: &[Token], position: &mut usize, open: &Location) -> Result<Expr, AssembleError> {
+        let token = tokens.get(*position).ok_or_else(|| AssembleError::new(open, "expected an expression"))?;
+        *position += 1;
+
+        match token.text.as_str() {
+            "(" => {
+                let expr = self.calc_expression(tokens, position, open)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => {
+                        *position += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(AssembleError::new(&token.location, "`(` is never closed")),
+                }
+            }
+            "-" => Ok(Expr::Unary(UnaryOp::Negate, Box::new(self.calc_term(tokens, position, open)?))),
+            "~" => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.calc_term(tokens, position, open)?))),
+            "HERE" => Ok(Expr::Number(self.backend.here() as i64)),
+            text => match self.number(text) {
+                Some(value) => Ok(Expr::Number(value)),
+                None => Ok(Expr::Symbol(text.to_string(), token.location.clone())),
+            },
+        }
+    }
+}
+
+fn is_keyword(text: &str) -> bool {
+    const KEYWORDS: [&str; 35] = [
+        "clear", "return", "lores", "hires", "scroll-down", "scroll-up", "scroll-right", "scroll-left", "plane", ";", "bcd", "save", "load", "sprite", "jump", "jump0", "native", "delay", "buzzer",
+        "i", "loop", "while", "again", "if", "then", "begin", "else", "end", "random", "key", "-key", "hex", "bighex", ":=", "{",
+    ];
+
+    KEYWORDS.contains(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        match compile(source, Rc::from("test.8o")) {
+            Ok(program) => program.rom,
+            Err(errors) => panic!("{}\n{}", source, errors[0]),
+        }
+    }
+
+    #[test]
+    fn loops_jump_back_and_exit_past_again() {
+        let source = ": main loop v0 += 1 while v0 != 5 again";
+        assert_eq!(rom(source), vec![
+            0x70, 0x01, // v0 += 1
+            0x40, 0x05, // skip the exit unless v0 == 5
+            0x12, 0x08, // jump past `again`
+            0x12, 0x00, // again
+        ]);
+    }
+
+    #[test]
+    fn if_else_jumps_over_the_branch_not_taken() {
+        let source = ": main if v0 == 1 begin v1 := 2 else v1 := 3 end";
+        assert_eq!(rom(source), vec![
+            0x30, 0x01, // skip the jump to `else` if v0 == 1
+            0x12, 0x08,
+            0x61, 0x02,
+            0x12, 0x0A, // jump past `end`
+            0x61, 0x03,
+        ]);
+    }
+
+    #[test]
+    fn macros_substitute_their_arguments() {
+        let source = ":macro set-both a b { v0 := a v1 := b } : main set-both 1 2 set-both 3 4";
+        assert_eq!(rom(source), vec![0x60, 0x01, 0x61, 0x02, 0x60, 0x03, 0x61, 0x04]);
+    }
+
+    #[test]
+    fn calc_evaluates_right_to_left() {
+        let source = ":calc x { 10 - 2 - 3 } :calc y { 2 * 3 + 1 } : main v0 := x v1 := y";
+        assert_eq!(rom(source), vec![0x60, 11, 0x61, 8]);
+    }
+
+    #[test]
+    fn aliases_and_constants_stand_in_for_registers_and_values() {
+        let source = ":alias counter v3 :const SPEED 4 : main counter := SPEED counter += 1";
+        assert_eq!(rom(source), vec![0x63, 0x04, 0x73, 0x01]);
+    }
+}
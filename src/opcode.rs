@@ -2,10 +2,17 @@ use std::fmt;
 
 use crate::emulator::{Address, Constant, Register};
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum Opcode {
     Call(Address),
     Clear,
     Return,
+    LowResolution,
+    HighResolution,
+    ScrollDown(Constant),
+    ScrollUp(Constant),
+    ScrollRight,
+    ScrollLeft,
     Goto(Address),
     CallFunction(Address),
     SkipEq(Register, Constant),
@@ -35,9 +42,11 @@ pub enum Opcode {
     SetSoundTimer(Register),
     IncrementAddressReg(Register),
     StoreSpriteAddress(Register),
+    StoreLargeSpriteAddress(Register),
     BinaryCodedDecimal(Register),
     Dump(Register),
     Load(Register),
+    SelectPlanes(Constant),
 }
 
 #[derive(Debug)]
@@ -53,8 +62,14 @@ impl Opcode {
         );
 
         let opcode = match nibbles {
-            (0x0, _, 0xE, 0x0) => Opcode::Clear,
-            (0x0, _, 0xE, 0xE) => Opcode::Return,
+            (0x0, 0x0, 0xE, 0x0) => Opcode::Clear,
+            (0x0, 0x0, 0xE, 0xE) => Opcode::Return,
+            (0x0, 0x0, 0xF, 0xE) => Opcode::LowResolution,
+            (0x0, 0x0, 0xF, 0xF) => Opcode::HighResolution,
+            (0x0, 0x0, 0xC, _) => Opcode::ScrollDown(nibbles.3),
+            (0x0, 0x0, 0xD, _) => Opcode::ScrollUp(nibbles.3),
+            (0x0, 0x0, 0xF, 0xB) => Opcode::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Opcode::ScrollLeft,
             (0x0, _, _, _) => Opcode::Call(build_address(nibbles)),
             (0x1, _, _, _) => Opcode::Goto(build_address(nibbles)),
             (0x2, _, _, _) => Opcode::CallFunction(build_address(nibbles)),
@@ -85,126 +100,279 @@ impl Opcode {
             (0xF, _, 0x1, 0x8) => Opcode::SetSoundTimer(nibbles.1),
             (0xF, _, 0x1, 0xE) => Opcode::IncrementAddressReg(nibbles.1),
             (0xF, _, 0x2, 0x9) => Opcode::StoreSpriteAddress(nibbles.1),
+            (0xF, _, 0x3, 0x0) => Opcode::StoreLargeSpriteAddress(nibbles.1),
             (0xF, _, 0x3, 0x3) => Opcode::BinaryCodedDecimal(nibbles.1),
             (0xF, _, 0x5, 0x5) => Opcode::Dump(nibbles.1),
             (0xF, _, 0x6, 0x5) => Opcode::Load(nibbles.1),
+            // XO-CHIP only has two planes to select from
+            (0xF, planes, 0x0, 0x1) if planes <= 0x3 => Opcode::SelectPlanes(planes),
             _ => return Err(build_data(nibbles))
         };
 
         Ok(opcode)
     }
+
+    /// Encodes the opcode back into the 16-bit word `Opcode::from` would decode it from.
+    ///
+    /// Operands too wide for their field are rejected rather than cut down, as are machine code
+    /// calls to the addresses whose `0NNN` words are other instructions, so the word always
+    /// decodes back into this opcode.
+    pub fn encode(self) -> Result<u16, String> {
+        let field = |value: u16, maximum: u16, name: &str| {
+            if value <= maximum {
+                Ok(value)
+            } else {
+                Err(format!("{:#x} does not fit in the {} of {}", value, name, self.pattern()))
+            }
+        };
+
+        let address = |address: Address| field(address, 0xFFF, "address");
+        let register = |register: Register| field(register as u16, 0xF, "register");
+        let registers = |first: Register, second: Register| -> Result<u16, String> {
+            Ok((register(first)? << 8) | (register(second)? << 4))
+        };
+        let constant = |first: Register, constant: Constant| -> Result<u16, String> {
+            Ok((register(first)? << 8) | constant as u16)
+        };
+
+        let word = match self {
+            Opcode::Call(target @ (0x0C0..=0x0DF | 0x0E0 | 0x0EE | 0x0FB | 0x0FC | 0x0FE | 0x0FF)) => {
+                return Err(format!("machine code at {:#05x} cannot be called, {:04X} is another instruction", target, target));
+            }
+            Opcode::Call(target) => address(target)?,
+            Opcode::Clear => 0x00E0,
+            Opcode::Return => 0x00EE,
+            Opcode::LowResolution => 0x00FE,
+            Opcode::HighResolution => 0x00FF,
+            Opcode::ScrollDown(rows) => 0x00C0 | field(rows as u16, 0xF, "row count")?,
+            Opcode::ScrollUp(rows) => 0x00D0 | field(rows as u16, 0xF, "row count")?,
+            Opcode::ScrollRight => 0x00FB,
+            Opcode::ScrollLeft => 0x00FC,
+            Opcode::Goto(target) => 0x1000 | address(target)?,
+            Opcode::CallFunction(target) => 0x2000 | address(target)?,
+            Opcode::SkipEq(first, value) => 0x3000 | constant(first, value)?,
+            Opcode::SkipNEq(first, value) => 0x4000 | constant(first, value)?,
+            Opcode::SkipEqReg(first, second) => 0x5000 | registers(first, second)?,
+            Opcode::Set(first, value) => 0x6000 | constant(first, value)?,
+            Opcode::AddAssign(first, value) => 0x7000 | constant(first, value)?,
+            Opcode::Copy(first, second) => 0x8000 | registers(first, second)?,
+            Opcode::Or(first, second) => 0x8001 | registers(first, second)?,
+            Opcode::And(first, second) => 0x8002 | registers(first, second)?,
+            Opcode::Xor(first, second) => 0x8003 | registers(first, second)?,
+            Opcode::AddAssignReg(first, second) => 0x8004 | registers(first, second)?,
+            Opcode::SubAssignReg(first, second) => 0x8005 | registers(first, second)?,
+            Opcode::ShiftRight(first, second) => 0x8006 | registers(first, second)?,
+            Opcode::Subtract(first, second) => 0x8007 | registers(first, second)?,
+            Opcode::ShiftLeft(first, second) => 0x800E | registers(first, second)?,
+            Opcode::SkipNEqReg(first, second) => 0x9000 | registers(first, second)?,
+            Opcode::SetAddressReg(target) => 0xA000 | address(target)?,
+            Opcode::JumpOffset(target) => 0xB000 | address(target)?,
+            Opcode::SetRand(first, value) => 0xC000 | constant(first, value)?,
+            Opcode::Draw(first, second, height) => 0xD000 | registers(first, second)? | field(height as u16, 0xF, "height")?,
+            Opcode::SkipKeyPress(first) => 0xE09E | constant(first, 0)?,
+            Opcode::SkipNoKeyPress(first) => 0xE0A1 | constant(first, 0)?,
+            Opcode::StoreDelayTimer(first) => 0xF007 | constant(first, 0)?,
+            Opcode::StoreKeypress(first) => 0xF00A | constant(first, 0)?,
+            Opcode::SetDelayTimer(first) => 0xF015 | constant(first, 0)?,
+            Opcode::SetSoundTimer(first) => 0xF018 | constant(first, 0)?,
+            Opcode::IncrementAddressReg(first) => 0xF01E | constant(first, 0)?,
+            Opcode::StoreSpriteAddress(first) => 0xF029 | constant(first, 0)?,
+            Opcode::StoreLargeSpriteAddress(first) => 0xF030 | constant(first, 0)?,
+            Opcode::BinaryCodedDecimal(first) => 0xF033 | constant(first, 0)?,
+            Opcode::Dump(first) => 0xF055 | constant(first, 0)?,
+            Opcode::Load(first) => 0xF065 | constant(first, 0)?,
+            Opcode::SelectPlanes(planes) => 0xF001 | (field(planes as u16, 0x3, "plane mask")? << 8),
+        };
+
+        Ok(word)
+    }
+
+    pub fn to_bytes(self) -> Result<[u8; 2], String> {
+        let word = self.encode()?;
+        Ok([(word >> 8) as u8, word as u8])
+    }
+
+    /// The conventional name of the instruction's encoding, e.g. `8XY4`.
+    pub fn pattern(self) -> &'static str {
+        match self {
+            Opcode::Call(_) => "0NNN",
+            Opcode::Clear => "00E0",
+            Opcode::Return => "00EE",
+            Opcode::LowResolution => "00FE",
+            Opcode::HighResolution => "00FF",
+            Opcode::ScrollDown(_) => "00CN",
+            Opcode::ScrollUp(_) => "00DN",
+            Opcode::ScrollRight => "00FB",
+            Opcode::ScrollLeft => "00FC",
+            Opcode::Goto(_) => "1NNN",
+            Opcode::CallFunction(_) => "2NNN",
+            Opcode::SkipEq(..) => "3XNN",
+            Opcode::SkipNEq(..) => "4XNN",
+            Opcode::SkipEqReg(..) => "5XY0",
+            Opcode::Set(..) => "6XNN",
+            Opcode::AddAssign(..) => "7XNN",
+            Opcode::Copy(..) => "8XY0",
+            Opcode::Or(..) => "8XY1",
+            Opcode::And(..) => "8XY2",
+            Opcode::Xor(..) => "8XY3",
+            Opcode::AddAssignReg(..) => "8XY4",
+            Opcode::SubAssignReg(..) => "8XY5",
+            Opcode::ShiftRight(..) => "8XY6",
+            Opcode::Subtract(..) => "8XY7",
+            Opcode::ShiftLeft(..) => "8XYE",
+            Opcode::SkipNEqReg(..) => "9XY0",
+            Opcode::SetAddressReg(_) => "ANNN",
+            Opcode::JumpOffset(_) => "BNNN",
+            Opcode::SetRand(..) => "CXNN",
+            Opcode::Draw(..) => "DXYN",
+            Opcode::SkipKeyPress(_) => "EX9E",
+            Opcode::SkipNoKeyPress(_) => "EXA1",
+            Opcode::StoreDelayTimer(_) => "FX07",
+            Opcode::StoreKeypress(_) => "FX0A",
+            Opcode::SetDelayTimer(_) => "FX15",
+            Opcode::SetSoundTimer(_) => "FX18",
+            Opcode::IncrementAddressReg(_) => "FX1E",
+            Opcode::StoreSpriteAddress(_) => "FX29",
+            Opcode::StoreLargeSpriteAddress(_) => "FX30",
+            Opcode::BinaryCodedDecimal(_) => "FX33",
+            Opcode::Dump(_) => "FX55",
+            Opcode::Load(_) => "FX65",
+            Opcode::SelectPlanes(_) => "FN01",
+        }
+    }
 }
 
-impl fmt::Debug for Opcode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+#[derive(Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Classic,
+    Octo,
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name {
+            "classic" => Some(Syntax::Classic),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+}
+
+impl Opcode {
+    /// Renders the instruction as assembly source, using `address` to name any address operands.
+    pub fn render(&self, syntax: Syntax, address: &dyn Fn(Address) -> String) -> String {
+        match syntax {
+            Syntax::Classic => self.render_classic(address),
+            Syntax::Octo => self.render_octo(address),
+        }
+    }
+
+    fn render_classic(&self, address: &dyn Fn(Address) -> String) -> String {
         match *self {
-            Opcode::Call(address) => {
-                write!(f, "CALL\t{:#03x}", address)
-            }
-            Opcode::Clear => {
-                write!(f, "CLS")
-            }
-            Opcode::Return => {
-                write!(f, "RET")
-            }
-            Opcode::Goto(address) => {
-                write!(f, "JMP\t{:#03x}", address)
-            }
-            Opcode::CallFunction(address) => {
-                write!(f, "CALL\t{:#03x}", address)
-            }
-            Opcode::SkipEq(register, constant) => {
-                write!(f, "SE\tV{:X}\t{}", register, constant)
-            }
-            Opcode::SkipNEq(register, constant) => {
-                write!(f, "SNE\tV{:X}\t{}", register, constant)
-            }
-            Opcode::SkipEqReg(first, second) => {
-                write!(f, "SE\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::Set(register, constant) => {
-                write!(f, "SET\tV{:X}\t{}", register, constant)
-            }
-            Opcode::AddAssign(register, constant) => {
-                write!(f, "ADDA\tV{:X}\t{}", register, constant)
-            }
-            Opcode::Copy(to, from) => {
-                write!(f, "SET\tV{:X}\tV{:X}", to, from)
-            }
-            Opcode::Or(first, second) => {
-                write!(f, "OR\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::And(first, second) => {
-                write!(f, "AND\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::Xor(first, second) => {
-                write!(f, "XOR\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::AddAssignReg(first, second) => {
-                write!(f, "ADDA\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::SubAssignReg(first, second) => {
-                write!(f, "SUBA\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::ShiftRight(first, second) => {
-                write!(f, "SHR\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::Subtract(first, second) => {
-                write!(f, "SUB\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::ShiftLeft(first, second) => {
-                write!(f, "SHL\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::SkipNEqReg(first, second) => {
-                write!(f, "SNE\tV{:X}\tV{:X}", first, second)
-            }
-            Opcode::SetAddressReg(address) => {
-                write!(f, "SET\tI\t{:#03x}", address)
-            }
-            Opcode::JumpOffset(address) => {
-                write!(f, "OFF\t{:#03x}", address)
-            }
-            Opcode::SetRand(register, constant) => {
-                write!(f, "RND\tV{:X}\t{}", register, constant)
-            }
-            Opcode::Draw(first, second, constant) => {
-                write!(f, "DRW\tV{:X}\tV{:X}\t{}", first, second, constant)
-            }
-            Opcode::SkipKeyPress(register) => {
-                write!(f, "SKP\tV{:X}", register)
-            }
-            Opcode::SkipNoKeyPress(register) => {
-                write!(f, "SNKP\tV{:X}", register)
-            }
-            Opcode::StoreDelayTimer(register) => {
-                write!(f, "SET\tV{:X}\tDELAY", register)
-            }
-            Opcode::StoreKeypress(register) => {
-                write!(f, "SET\tV{:X}\tKEY", register)
-            }
-            Opcode::SetDelayTimer(register) => {
-                write!(f, "SET\tDELAY\tV{:X}", register)
-            }
-            Opcode::SetSoundTimer(register) => {
-                write!(f, "SET\tSOUND\tV{:X}", register)
-            }
-            Opcode::IncrementAddressReg(register) => {
-                write!(f, "ADD\tI\tV{:X}", register)
-            }
-            Opcode::StoreSpriteAddress(register) => {
-                write!(f, "SPRT\tV{:X}", register)
-            }
-            Opcode::BinaryCodedDecimal(register) => {
-                write!(f, "BCD\tV{:X}", register)
-            }
-            Opcode::Dump(register) => {
-                write!(f, "DUMP\tV{:X}", register)
-            }
-            Opcode::Load(register) => {
-                write!(f, "LOAD\tV{:X}", register)
-            }
+            Opcode::Call(target) => format!("SYS {}", address(target)),
+            Opcode::Clear => "CLS".to_string(),
+            Opcode::Return => "RET".to_string(),
+            Opcode::LowResolution => "LOW".to_string(),
+            Opcode::HighResolution => "HIGH".to_string(),
+            Opcode::ScrollDown(rows) => format!("SCD {}", rows),
+            Opcode::ScrollUp(rows) => format!("SCU {}", rows),
+            Opcode::ScrollRight => "SCR".to_string(),
+            Opcode::ScrollLeft => "SCL".to_string(),
+            Opcode::Goto(target) => format!("JP {}", address(target)),
+            Opcode::CallFunction(target) => format!("CALL {}", address(target)),
+            Opcode::SkipEq(register, constant) => format!("SE V{:X}, {:#04x}", register, constant),
+            Opcode::SkipNEq(register, constant) => format!("SNE V{:X}, {:#04x}", register, constant),
+            Opcode::SkipEqReg(first, second) => format!("SE V{:X}, V{:X}", first, second),
+            Opcode::Set(register, constant) => format!("LD V{:X}, {:#04x}", register, constant),
+            Opcode::AddAssign(register, constant) => format!("ADD V{:X}, {:#04x}", register, constant),
+            Opcode::Copy(to, from) => format!("LD V{:X}, V{:X}", to, from),
+            Opcode::Or(first, second) => format!("OR V{:X}, V{:X}", first, second),
+            Opcode::And(first, second) => format!("AND V{:X}, V{:X}", first, second),
+            Opcode::Xor(first, second) => format!("XOR V{:X}, V{:X}", first, second),
+            Opcode::AddAssignReg(first, second) => format!("ADD V{:X}, V{:X}", first, second),
+            Opcode::SubAssignReg(first, second) => format!("SUB V{:X}, V{:X}", first, second),
+            Opcode::ShiftRight(first, second) => format!("SHR V{:X}, V{:X}", first, second),
+            Opcode::Subtract(first, second) => format!("SUBN V{:X}, V{:X}", first, second),
+            Opcode::ShiftLeft(first, second) => format!("SHL V{:X}, V{:X}", first, second),
+            Opcode::SkipNEqReg(first, second) => format!("SNE V{:X}, V{:X}", first, second),
+            Opcode::SetAddressReg(target) => format!("LD I, {}", address(target)),
+            Opcode::JumpOffset(target) => format!("JP V0, {}", address(target)),
+            Opcode::SetRand(register, constant) => format!("RND V{:X}, {:#04x}", register, constant),
+            Opcode::Draw(first, second, constant) => format!("DRW V{:X}, V{:X}, {:#x}", first, second, constant),
+            Opcode::SkipKeyPress(register) => format!("SKP V{:X}", register),
+            Opcode::SkipNoKeyPress(register) => format!("SKNP V{:X}", register),
+            Opcode::StoreDelayTimer(register) => format!("LD V{:X}, DT", register),
+            Opcode::StoreKeypress(register) => format!("LD V{:X}, K", register),
+            Opcode::SetDelayTimer(register) => format!("LD DT, V{:X}", register),
+            Opcode::SetSoundTimer(register) => format!("LD ST, V{:X}", register),
+            Opcode::IncrementAddressReg(register) => format!("ADD I, V{:X}", register),
+            Opcode::StoreSpriteAddress(register) => format!("LD F, V{:X}", register),
+            Opcode::StoreLargeSpriteAddress(register) => format!("LD HF, V{:X}", register),
+            Opcode::BinaryCodedDecimal(register) => format!("LD B, V{:X}", register),
+            Opcode::Dump(register) => format!("LD [I], V{:X}", register),
+            Opcode::Load(register) => format!("LD V{:X}, [I]", register),
+            Opcode::SelectPlanes(planes) => format!("PLANE {}", planes),
         }
     }
+
+    fn render_octo(&self, address: &dyn Fn(Address) -> String) -> String {
+        match *self {
+            // Octo has no mnemonic for machine code routines, so emit the raw bytes
+            Opcode::Call(target) => format!("{:#04x} {:#04x}", target >> 8, target & 0xFF),
+            Opcode::Clear => "clear".to_string(),
+            Opcode::Return => "return".to_string(),
+            Opcode::LowResolution => "lores".to_string(),
+            Opcode::HighResolution => "hires".to_string(),
+            Opcode::ScrollDown(rows) => format!("scroll-down {}", rows),
+            Opcode::ScrollUp(rows) => format!("scroll-up {}", rows),
+            Opcode::ScrollRight => "scroll-right".to_string(),
+            Opcode::ScrollLeft => "scroll-left".to_string(),
+            Opcode::Goto(target) => format!("jump {}", address(target)),
+            Opcode::CallFunction(target) => format!(":call {}", address(target)),
+            Opcode::SkipEq(register, constant) => format!("if v{:x} != {:#04x} then", register, constant),
+            Opcode::SkipNEq(register, constant) => format!("if v{:x} == {:#04x} then", register, constant),
+            Opcode::SkipEqReg(first, second) => format!("if v{:x} != v{:x} then", first, second),
+            Opcode::Set(register, constant) => format!("v{:x} := {:#04x}", register, constant),
+            Opcode::AddAssign(register, constant) => format!("v{:x} += {:#04x}", register, constant),
+            Opcode::Copy(to, from) => format!("v{:x} := v{:x}", to, from),
+            Opcode::Or(first, second) => format!("v{:x} |= v{:x}", first, second),
+            Opcode::And(first, second) => format!("v{:x} &= v{:x}", first, second),
+            Opcode::Xor(first, second) => format!("v{:x} ^= v{:x}", first, second),
+            Opcode::AddAssignReg(first, second) => format!("v{:x} += v{:x}", first, second),
+            Opcode::SubAssignReg(first, second) => format!("v{:x} -= v{:x}", first, second),
+            Opcode::ShiftRight(first, second) => format!("v{:x} >>= v{:x}", first, second),
+            Opcode::Subtract(first, second) => format!("v{:x} =- v{:x}", first, second),
+            Opcode::ShiftLeft(first, second) => format!("v{:x} <<= v{:x}", first, second),
+            Opcode::SkipNEqReg(first, second) => format!("if v{:x} == v{:x} then", first, second),
+            Opcode::SetAddressReg(target) => format!("i := {}", address(target)),
+            Opcode::JumpOffset(target) => format!("jump0 {}", address(target)),
+            Opcode::SetRand(register, constant) => format!("v{:x} := random {:#04x}", register, constant),
+            Opcode::Draw(first, second, constant) => format!("sprite v{:x} v{:x} {:#x}", first, second, constant),
+            Opcode::SkipKeyPress(register) => format!("if v{:x} -key then", register),
+            Opcode::SkipNoKeyPress(register) => format!("if v{:x} key then", register),
+            Opcode::StoreDelayTimer(register) => format!("v{:x} := delay", register),
+            Opcode::StoreKeypress(register) => format!("v{:x} := key", register),
+            Opcode::SetDelayTimer(register) => format!("delay := v{:x}", register),
+            Opcode::SetSoundTimer(register) => format!("buzzer := v{:x}", register),
+            Opcode::IncrementAddressReg(register) => format!("i += v{:x}", register),
+            Opcode::StoreSpriteAddress(register) => format!("i := hex v{:x}", register),
+            Opcode::StoreLargeSpriteAddress(register) => format!("i := bighex v{:x}", register),
+            Opcode::BinaryCodedDecimal(register) => format!("bcd v{:x}", register),
+            Opcode::Dump(register) => format!("save v{:x}", register),
+            Opcode::Load(register) => format!("load v{:x}", register),
+            Opcode::SelectPlanes(planes) => format!("plane {}", planes),
+        }
+    }
+}
+
+pub fn hex_address(address: Address) -> String {
+    format!("{:#05x}", address)
+}
+
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(Syntax::Classic, &hex_address))
+    }
 }
 
 fn build_data(nibbles: (u8, u8, u8, u8)) -> (Data, Data) {
@@ -230,4 +398,51 @@ fn build_address(nibbles: (u8, u8, u8, u8)) -> Address {
 
 fn build_constant(nibbles: (u8, u8, u8, u8)) -> Constant {
     (nibbles.2 << 4) | nibbles.3
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(word: u16) -> Option<Opcode> {
+        Opcode::from((word >> 8) as u8, word as u8).ok()
+    }
+
+    #[test]
+    fn every_decoded_word_encodes_back_to_itself() {
+        for word in 0..=0xFFFF {
+            if let Some(opcode) = decode(word) {
+                assert_eq!(opcode.encode(), Ok(word), "{:04X} decoded as {:?}", word, opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn every_decoded_opcode_decodes_back_from_its_encoding() {
+        for word in 0..=0xFFFF {
+            if let Some(opcode) = decode(word) {
+                let encoded = opcode.encode().unwrap();
+                assert!(decode(encoded) == Some(opcode), "{:?} encoded as {:04X}", opcode, encoded);
+                assert_eq!(opcode.to_bytes(), Ok([(word >> 8) as u8, word as u8]));
+            }
+        }
+    }
+
+    #[test]
+    fn machine_code_calls_to_other_instructions_are_rejected() {
+        for target in [0x0C0, 0x0CF, 0x0D0, 0x0DF, 0x0E0, 0x0EE, 0x0FB, 0x0FC, 0x0FE, 0x0FF].iter() {
+            assert!(Opcode::Call(*target).encode().is_err(), "SYS {:#05x}", target);
+        }
+
+        assert_eq!(Opcode::Call(0x0E1).encode(), Ok(0x00E1));
+    }
+
+    #[test]
+    fn operands_too_wide_for_their_field_are_rejected() {
+        assert!(Opcode::Goto(0x1000).encode().is_err());
+        assert!(Opcode::Set(0x10, 0).encode().is_err());
+        assert!(Opcode::Copy(0, 0x10).encode().is_err());
+        assert!(Opcode::Draw(0, 0, 0x10).encode().is_err());
+        assert!(Opcode::SelectPlanes(4).encode().is_err());
+    }
+}
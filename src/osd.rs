@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use crate::memory::{ascii_glyph, GLYPH_HEIGHT};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+const LETTER_SPACING: usize = 1;
+
+/// Status text drawn over the screen: a status line which stays until it's cleared, like `PAUSED`,
+/// and a message which fades after a couple of seconds, like a speed change.
+#[derive(Default)]
+pub struct Osd {
+    status: Option<String>,
+    message: Option<(String, Instant)>,
+}
+
+impl Osd {
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some((message, Instant::now() + MESSAGE_DURATION));
+    }
+
+    /// Forgets a message which has been shown long enough, returning whether one went away.
+    pub fn expire(&mut self) -> bool {
+        match self.message {
+            Some((_, expires)) if expires <= Instant::now() => {
+                self.message = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The lines to draw, top first.
+    pub fn lines(&self) -> Vec<&str> {
+        self.status.iter()
+            .map(String::as_str)
+            .chain(self.message.iter().map(|(message, _)| message.as_str()))
+            .collect()
+    }
+}
+
+/// Lays `text` out in the 6-bit ASCII font, returning the lit pixels and the size of the text.
+///
+/// The font only has upper case letters, so lower case ones are drawn as upper case, and anything
+/// else outside it as `?`.
+pub fn layout_text(text: &str) -> (Vec<(usize, usize)>, (usize, usize)) {
+    let mut pixels = vec![];
+    let mut x = 0;
+
+    for character in text.chars() {
+        let (rows, width) = ascii_glyph(character.to_ascii_uppercase())
+            .or_else(|| ascii_glyph('?'))
+            .unwrap();
+
+        for (y, row) in rows.iter().enumerate() {
+            for column in 0..8 {
+                if row & (0x80 >> column) != 0 {
+                    pixels.push((x + column, y));
+                }
+            }
+        }
+
+        x += width + LETTER_SPACING;
+    }
+
+    (pixels, (x.saturating_sub(LETTER_SPACING), GLYPH_HEIGHT))
+}
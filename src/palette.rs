@@ -0,0 +1,136 @@
+use sdl2::pixels::Color;
+
+/// The built-in themes, in the order the palette hotkey cycles through them.
+pub const THEMES: [&str; 6] = ["alvin", "classic", "amber", "octo", "high-contrast", "colorblind"];
+
+/// The colours pixels are drawn in, indexed by the planes they're lit on: the background, the
+/// first plane, the second plane and both planes.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub colors: [Color; 4],
+}
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Palette> {
+        let colors = match name {
+            "alvin" => [0x353B73, 0xFFFF29, 0xE0602C, 0x7B4E30],
+            "classic" => [0x0B1A0B, 0x33FF33, 0x1F9F1F, 0xA0FFA0],
+            "amber" => [0x1A1000, 0xFFB000, 0x9F6A00, 0xFFE0A0],
+            "octo" => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            "high-contrast" => [0x000000, 0xFFFFFF, 0x808080, 0xFFFF00],
+            // Okabe and Ito's palette, which stays distinct with the common kinds of colour blindness
+            "colorblind" => [0x000000, 0xE69F00, 0x56B4E9, 0x009E73],
+            _ => return None,
+        };
+
+        Some(Palette { colors: [rgb(colors[0]), rgb(colors[1]), rgb(colors[2]), rgb(colors[3])] })
+    }
+
+    /// Parses a theme name or a comma separated list of `#rrggbb` colours, background first.
+    /// Colours left out are taken from the default theme.
+    pub fn parse(palette: &str) -> Option<Palette> {
+        if let Some(theme) = Palette::from_name(palette) {
+            return Some(theme);
+        }
+
+        let colors = palette.split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect::<Option<Vec<_>>>()?;
+
+        Palette::from_colors(&colors)
+    }
+
+    /// A palette from up to four colours, which needs at least a background and a foreground.
+    pub fn from_colors(colors: &[Color]) -> Option<Palette> {
+        if colors.len() < 2 || colors.len() > 4 {
+            return None;
+        }
+
+        let mut palette = Palette::default();
+        palette.colors[..colors.len()].copy_from_slice(colors);
+        Some(palette)
+    }
+
+    pub fn background(&self) -> Color {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.colors[1]
+    }
+
+    /// The colour of a pixel lit on the planes in `planes`.
+    pub fn color(&self, planes: u8) -> Color {
+        self.colors[planes as usize & 0x3]
+    }
+
+    /// The theme after this one, or the first theme for a custom palette.
+    pub fn next_theme(&self) -> (&'static str, Palette) {
+        let current = THEMES.iter().position(|theme| Palette::from_name(theme).as_ref() == Some(self));
+        let next = THEMES[current.map(|current| (current + 1) % THEMES.len()).unwrap_or(0)];
+
+        (next, Palette::from_name(next).unwrap())
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::from_name(THEMES[0]).unwrap()
+    }
+}
+
+fn rgb(value: u32) -> Color {
+    Color::RGB((value >> 16) as u8, (value >> 8) as u8, value as u8)
+}
+
+/// Parses a `#rrggbb` colour, the leading `#` being optional.
+pub fn parse_color(hex: &str) -> Option<Color> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+
+    u32::from_str_radix(hex, 16).ok().map(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_parse_from_themes_or_colours() {
+        for theme in THEMES.iter() {
+            assert!(Palette::parse(theme) == Palette::from_name(theme));
+        }
+
+        let default = Palette::default();
+        let two = Palette::parse("#000000, #ffffff").unwrap();
+        assert_eq!(two.colors, [Color::RGB(0, 0, 0), Color::RGB(0xFF, 0xFF, 0xFF), default.colors[2], default.colors[3]]);
+
+        let four = Palette::parse("102030,#405060,708090,#a0b0c0").unwrap();
+        assert_eq!(four.colors, [
+            Color::RGB(0x10, 0x20, 0x30),
+            Color::RGB(0x40, 0x50, 0x60),
+            Color::RGB(0x70, 0x80, 0x90),
+            Color::RGB(0xA0, 0xB0, 0xC0),
+        ]);
+
+        assert!(Palette::parse("#000000").is_none());
+        assert!(Palette::parse("#000000,#111111,#222222,#333333,#444444").is_none());
+        assert!(Palette::parse("#000000,#12345").is_none());
+        assert!(Palette::parse("no-such-theme").is_none());
+    }
+
+    #[test]
+    fn themes_cycle_back_to_the_first() {
+        let (first, _) = Palette::from_name(THEMES[THEMES.len() - 1]).unwrap().next_theme();
+        assert_eq!(first, THEMES[0]);
+
+        let (second, palette) = Palette::default().next_theme();
+        assert_eq!(second, THEMES[1]);
+        assert!(palette == Palette::from_name(THEMES[1]).unwrap());
+
+        let custom = Palette::parse("#010203,#040506").unwrap();
+        assert_eq!(custom.next_theme().0, THEMES[0]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use sdl2::pixels::Color;
+
+use crate::{
+    framebuffer::Framebuffer,
+    palette::Palette,
+};
+
+/// How often the display shows a frame, which decay is measured in.
+const FRAME_RATE: f64 = 60.0;
+pub const DEFAULT_HALF_LIFE: Duration = Duration::from_millis(30);
+/// How close a fading pixel has to be to the background before it counts as faded.
+const SETTLED: f32 = 0.5;
+
+/// Ways of hiding the flicker of sprites being erased and redrawn, by letting pixels linger on
+/// screen for a while after they're turned off.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Persistence {
+    Off,
+    /// Shows pixels lit in either this frame or the last.
+    Or,
+    /// Fades pixels out like a CRT's phosphor, halving their brightness every half-life.
+    Phosphor(Duration),
+}
+
+impl Persistence {
+    pub fn from_name(name: &str, half_life: Duration) -> Option<Persistence> {
+        match name {
+            "off" => Some(Persistence::Off),
+            "or" => Some(Persistence::Or),
+            "phosphor" => Some(Persistence::Phosphor(half_life)),
+            _ => None,
+        }
+    }
+}
+
+/// Turns framebuffers into the colours shown for them, only ever affecting what's on screen.
+pub struct PersistenceFilter {
+    persistence: Persistence,
+    previous: Vec<u8>,
+    glow: Vec<[f32; 3]>,
+}
+
+impl PersistenceFilter {
+    pub fn new(persistence: Persistence) -> PersistenceFilter {
+        PersistenceFilter {
+            persistence,
+            previous: vec![],
+            glow: vec![],
+        }
+    }
+
+    /// Colours each pixel of a frame, returning whether the next frame would look different even
+    /// if the framebuffer doesn't change, so the display should keep presenting frames.
+    pub fn apply(&mut self, framebuffer: &Framebuffer, palette: &Palette, image: &mut Vec<Color>) -> bool {
+        let pixels = framebuffer.pixels();
+
+        // Resolution switches clear the screen, so there's nothing to linger
+        if self.previous.len() != pixels.len() {
+            self.previous = pixels.to_vec();
+            self.glow = pixels.iter().map(|pixel| channels(palette.color(*pixel))).collect();
+        }
+
+        image.clear();
+        let changing = match self.persistence {
+            Persistence::Off => {
+                image.extend(pixels.iter().map(|pixel| palette.color(*pixel)));
+                false
+            }
+            Persistence::Or => {
+                image.extend(pixels.iter().zip(self.previous.iter()).map(|(pixel, previous)| palette.color(pixel | previous)));
+                self.previous != pixels
+            }
+            Persistence::Phosphor(half_life) => {
+                let frames = half_life.as_secs_f64() * FRAME_RATE;
+                let decay = 0.5f64.powf(1.0 / frames.max(f64::EPSILON)) as f32;
+                let mut changing = false;
+
+                for (pixel, glow) in pixels.iter().zip(self.glow.iter_mut()) {
+                    let target = channels(palette.color(*pixel));
+
+                    for (channel, target) in glow.iter_mut().zip(target.iter()) {
+                        *channel = target + (*channel - target) * decay;
+                        changing |= (*channel - target).abs() > SETTLED;
+                    }
+
+                    // Lit pixels come on at full brightness, only fading out is gradual
+                    if *pixel != 0 {
+                        *glow = target;
+                    }
+
+                    image.push(Color::RGB(glow[0].round() as u8, glow[1].round() as u8, glow[2].round() as u8));
+                }
+
+                changing
+            }
+        };
+
+        self.previous.copy_from_slice(pixels);
+        changing
+    }
+}
+
+fn channels(color: Color) -> [f32; 3] {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit_framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(0, 0, &[0x80]);
+        framebuffer
+    }
+
+    #[test]
+    fn or_shows_the_previous_frame_once() {
+        let palette = Palette::default();
+        let mut filter = PersistenceFilter::new(Persistence::Or);
+        let mut image = vec![];
+
+        let mut framebuffer = lit_framebuffer();
+        assert!(!filter.apply(&framebuffer, &palette, &mut image));
+        assert_eq!(image[0], palette.color(1));
+
+        framebuffer.clear();
+        assert!(filter.apply(&framebuffer, &palette, &mut image));
+        assert_eq!(image[0], palette.color(1));
+
+        assert!(!filter.apply(&framebuffer, &palette, &mut image));
+        assert_eq!(image[0], palette.color(0));
+    }
+
+    #[test]
+    fn phosphor_halves_every_half_life_until_settled() {
+        let palette = Palette::default();
+        // Six frames at 60 fps
+        let mut filter = PersistenceFilter::new(Persistence::Phosphor(Duration::from_millis(100)));
+        let mut image = vec![];
+
+        let mut framebuffer = lit_framebuffer();
+        filter.apply(&framebuffer, &palette, &mut image);
+
+        framebuffer.clear();
+        for _ in 0..6 {
+            assert!(filter.apply(&framebuffer, &palette, &mut image));
+        }
+
+        let (lit, unlit) = (channels(palette.color(1)), channels(palette.color(0)));
+        let shown = channels(image[0]);
+        for channel in 0..3 {
+            let half = (lit[channel] + unlit[channel]) / 2.0;
+            assert!((shown[channel] - half).abs() <= 1.0, "{} is not about {}", shown[channel], half);
+        }
+
+        let frames = (0..100).position(|_| !filter.apply(&framebuffer, &palette, &mut image));
+        assert!(frames.is_some(), "never settled");
+
+        let shown = channels(image[0]);
+        for channel in 0..3 {
+            assert!((shown[channel] - unlit[channel]).abs() <= 1.0);
+        }
+    }
+}
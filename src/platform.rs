@@ -0,0 +1,84 @@
+use std::fmt;
+
+use crate::memory::Layout;
+
+/// The CHIP-8 variants programs are commonly written for.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+/// Behaviours which differ between interpreters, and which programs often depend on.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VY into VX rather than shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// `FX55`/`FX65` leave I pointing past the last register transferred.
+    pub load_store_increments_i: bool,
+    /// `BNNN` jumps to NNN plus VX, where X is the top nibble of NNN, instead of plus V0.
+    pub jump_uses_vx: bool,
+    /// `8XY1`, `8XY2` and `8XY3` clear VF.
+    pub logic_resets_vf: bool,
+    /// `DXYN` wraps sprites around the screen edges rather than clipping them.
+    pub wrap_sprites: bool,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// How many bytes of program the platform leaves room for from 0x200 up.
+    pub fn program_space(self) -> usize {
+        Layout::for_platform(self).program_space()
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: true,
+                wrap_sprites: false,
+            },
+            Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                wrap_sprites: false,
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: false,
+                wrap_sprites: true,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::SuperChip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Platform::Chip8.quirks()
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+use crate::{
+    framebuffer::{Framebuffer, HIGH_RESOLUTION},
+    palette::Palette,
+};
+
+pub const DEFAULT_RECORD_SCALE: usize = 4;
+/// Frames 2048x1024 pixels across, as large as anything is likely to show them.
+pub const MAX_RECORD_SCALE: usize = 16;
+/// The largest scale whose frames GIFs, sized in 16 bits, can still hold.
+const MAX_GIF_SCALE: usize = u16::MAX as usize / HIGH_RESOLUTION.0;
+const FRAME_RATE: u64 = 60;
+/// Browsers slow GIF frames shorter than this many hundredths of a second right down, so frames
+/// which would be shown for less are dropped instead.
+const MIN_GIF_DELAY: u64 = 2;
+const SAMPLE_RATE: u32 = 44100;
+/// The same beep `Sound` plays.
+const TONE: f32 = 440.0;
+const VOLUME: f32 = 0.25;
+const WAV_HEADER_SIZE: u32 = 44;
+
+/// Where recorded frames go.
+enum Output {
+    /// An animated GIF, with each frame held until the screen next changes.
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        /// The palette the recording started with, which frames only need their own palette to
+        /// differ from.
+        palette: Palette,
+        held: Option<HeldFrame>,
+        size: (u16, u16),
+    },
+    /// Raw RGB frames one after another.
+    Raw(BufWriter<File>),
+    /// Raw RGB frames written to the standard input of an encoder.
+    Pipe(Child),
+}
+
+struct HeldFrame {
+    pixels: Vec<u8>,
+    palette: Palette,
+    start: u64,
+}
+
+/// Captures every emulated frame, and optionally the beeper, so ROMs can be shown off outside
+/// the emulator.
+///
+/// Frames are always recorded at the high resolution times `scale`, with low resolution pixels
+/// doubled, so switching resolution mid-recording doesn't change the frame size.
+pub struct Recorder {
+    output: Output,
+    audio: Option<Wav>,
+    scale: usize,
+    frames: u64,
+}
+
+impl Recorder {
+    /// Starts recording to `target`: a GIF if it ends in `.gif`, raw frames into the shell
+    /// command after a leading `|`, and raw frames into the file otherwise. The beeper is
+    /// recorded to `audio` if given.
+    pub fn start(target: &str, palette: &Palette, audio: Option<&Path>, scale: usize) -> Result<Recorder, String> {
+        let scale = scale.max(1);
+        if scale > MAX_RECORD_SCALE {
+            return Err(format!("frames can be at most {} times larger than 128x64, not {}", MAX_RECORD_SCALE, scale));
+        }
+
+        let output = if let Some(command) = target.strip_prefix('|') {
+            let child = shell(command)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|error| format!("could not run `{}`: {}", command, error))?;
+            Output::Pipe(child)
+        } else {
+            let file = File::create(target).map_err(|error| format!("could not create {}: {}", target, error))?;
+            let file = BufWriter::new(file);
+
+            if Path::new(target).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif")) {
+                let (width, height) = gif_size(scale)?;
+                let mut encoder = gif::Encoder::new(file, width, height, &gif_palette(palette))
+                    .map_err(|error| format!("could not write {}: {}", target, error))?;
+                encoder.set_repeat(gif::Repeat::Infinite)
+                    .map_err(|error| format!("could not write {}: {}", target, error))?;
+
+                Output::Gif { encoder, palette: *palette, held: None, size: (width, height) }
+            } else {
+                Output::Raw(file)
+            }
+        };
+
+        let audio = match audio {
+            Some(path) => Some(Wav::create(path).map_err(|error| format!("could not write {}: {}", path.display(), error))?),
+            None => None,
+        };
+
+        Ok(Recorder {
+            output,
+            audio,
+            scale,
+            frames: 0,
+        })
+    }
+
+    /// The size of every recorded frame, which encoders reading raw frames need to be told.
+    pub fn frame_size(&self) -> (usize, usize) {
+        (HIGH_RESOLUTION.0 * self.scale, HIGH_RESOLUTION.1 * self.scale)
+    }
+
+    /// Records a 60th of a second showing `framebuffer`, with the beeper on if `beeping`.
+    pub fn capture(&mut self, framebuffer: &Framebuffer, palette: &Palette, beeping: bool) -> Result<(), String> {
+        let pixels = self.upscale(framebuffer);
+
+        match &mut self.output {
+            Output::Gif { encoder, palette: global, held, size } => {
+                let unchanged = held.as_ref().is_some_and(|held| held.pixels == pixels && held.palette == *palette);
+
+                if !unchanged {
+                    let start = match held.take() {
+                        // Replacing a frame too short to show rather than writing it
+                        Some(frame) if centiseconds(self.frames) - centiseconds(frame.start) < MIN_GIF_DELAY => frame.start,
+                        Some(frame) => {
+                            write_gif_frame(encoder, global, &frame, self.frames, *size)?;
+                            self.frames
+                        }
+                        None => self.frames,
+                    };
+
+                    *held = Some(HeldFrame { pixels, palette: *palette, start });
+                }
+            }
+            Output::Raw(file) => {
+                file.write_all(&rgb(&pixels, palette)).map_err(|error| error.to_string())?;
+            }
+            Output::Pipe(child) => {
+                let stdin = child.stdin.as_mut().unwrap();
+                stdin.write_all(&rgb(&pixels, palette)).map_err(|error| format!("the encoder stopped reading: {}", error))?;
+            }
+        }
+
+        if let Some(audio) = &mut self.audio {
+            audio.write_frame(self.frames, beeping).map_err(|error| error.to_string())?;
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes out whatever is buffered and waits for the encoder, if there is one.
+    pub fn finish(self) -> Result<(), String> {
+        match self.output {
+            Output::Gif { mut encoder, palette, held, size } => {
+                if let Some(frame) = held {
+                    write_gif_frame(&mut encoder, &palette, &frame, self.frames.max(frame.start + 1), size)?;
+                }
+
+                encoder.into_inner()
+                    .and_then(|mut file| file.flush())
+                    .map_err(|error| error.to_string())?;
+            }
+            Output::Raw(mut file) => file.flush().map_err(|error| error.to_string())?,
+            Output::Pipe(mut child) => {
+                // Closing its input tells the encoder the recording is over
+                drop(child.stdin.take());
+
+                let status = child.wait().map_err(|error| error.to_string())?;
+                if !status.success() {
+                    return Err(format!("the encoder exited with {}", status));
+                }
+            }
+        }
+
+        if let Some(audio) = self.audio {
+            audio.finish().map_err(|error| error.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// The planes lit at each pixel of the recorded frame.
+    fn upscale(&self, framebuffer: &Framebuffer) -> Vec<u8> {
+        let (width, height) = framebuffer.dimensions();
+        let factor = HIGH_RESOLUTION.0 / width * self.scale;
+        let pixels = framebuffer.pixels();
+
+        let mut scaled = Vec::with_capacity(width * height * factor * factor);
+        for y in 0..height * factor {
+            for x in 0..width * factor {
+                scaled.push(pixels[(y / factor) * width + x / factor]);
+            }
+        }
+
+        scaled
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// The width and height of frames recorded at `scale`, if they fit in the 16 bits GIFs have.
+fn gif_size(scale: usize) -> Result<(u16, u16), String> {
+    let size = |pixels: usize| pixels.checked_mul(scale).and_then(|size| u16::try_from(size).ok());
+
+    match (size(HIGH_RESOLUTION.0), size(HIGH_RESOLUTION.1)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(format!("GIF frames can be at most {} times larger than 128x64, not {}", MAX_GIF_SCALE, scale)),
+    }
+}
+
+/// How many hundredths of a second into the recording `frame` starts, which is what GIF delays
+/// count in.
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + FRAME_RATE / 2) / FRAME_RATE
+}
+
+/// Writes `frame`, shown until `end`. Pixels are already palette indices, the planes they're
+/// lit on, so palettes only need four colours.
+fn write_gif_frame(
+    encoder: &mut gif::Encoder<BufWriter<File>>,
+    global: &Palette,
+    frame: &HeldFrame,
+    end: u64,
+    (width, height): (u16, u16),
+) -> Result<(), String> {
+    let delay = centiseconds(end) - centiseconds(frame.start);
+
+    let gif_frame = gif::Frame {
+        width,
+        height,
+        delay: u16::try_from(delay).unwrap_or(u16::MAX),
+        palette: if frame.palette == *global { None } else { Some(gif_palette(&frame.palette)) },
+        buffer: frame.pixels.as_slice().into(),
+        ..gif::Frame::default()
+    };
+
+    encoder.write_frame(&gif_frame).map_err(|error| error.to_string())
+}
+
+fn gif_palette(palette: &Palette) -> Vec<u8> {
+    palette.colors.iter().flat_map(|color| vec![color.r, color.g, color.b]).collect()
+}
+
+fn rgb(pixels: &[u8], palette: &Palette) -> Vec<u8> {
+    pixels.iter()
+        .flat_map(|pixel| {
+            let color = palette.color(*pixel);
+            vec![color.r, color.g, color.b]
+        })
+        .collect()
+}
+
+/// A mono 16 bit WAV file, whose header is filled in once its length is known.
+struct Wav {
+    file: BufWriter<File>,
+    samples: u32,
+}
+
+impl Wav {
+    fn create(path: &Path) -> std::io::Result<Wav> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&[0; WAV_HEADER_SIZE as usize])?;
+
+        Ok(Wav { file, samples: 0 })
+    }
+
+    /// Writes the samples for `frame`, a square wave if `beeping` and silence otherwise.
+    fn write_frame(&mut self, frame: u64, beeping: bool) -> std::io::Result<()> {
+        let end = ((frame + 1) * SAMPLE_RATE as u64 / FRAME_RATE) as u32;
+
+        while self.samples < end {
+            // Timed from the start of the recording so the wave carries on across frames
+            let phase = (self.samples as f32 * TONE / SAMPLE_RATE as f32) % 1.0;
+            let sample = match (beeping, phase < 0.5) {
+                (false, _) => 0,
+                (true, true) => (VOLUME * i16::MAX as f32) as i16,
+                (true, false) => -(VOLUME * i16::MAX as f32) as i16,
+            };
+
+            self.file.write_all(&sample.to_le_bytes())?;
+            self.samples += 1;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        let data_size = self.samples * 2;
+
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // Uncompressed, one channel
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        header.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        // Two bytes a sample, sixteen bits of them
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.flush()
+    }
+}
+
+/// Where the audio for a recording to `target` goes: beside it with a `.wav` extension, or
+/// named after the ROM when the frames go to an encoder.
+pub fn audio_path(target: &str, name: &str, timestamp: &str) -> PathBuf {
+    if target.starts_with('|') {
+        PathBuf::from(format!("{}-{}.wav", name, timestamp))
+    } else {
+        Path::new(target).with_extension("wav")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("alvin-recorder-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn frames_too_large_for_a_gif_are_refused() {
+        assert_eq!(gif_size(1).unwrap(), (128, 64));
+        assert_eq!(gif_size(MAX_GIF_SCALE).unwrap(), (65408, 32704));
+        assert!(gif_size(MAX_GIF_SCALE + 1).is_err());
+        assert!(gif_size(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn scales_past_the_cap_are_refused_for_every_output() {
+        let path = temp_path("large.rgb");
+        assert!(Recorder::start(path.to_str().unwrap(), &Palette::default(), None, MAX_RECORD_SCALE + 1).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn frames_are_timed_in_rounded_hundredths_of_a_second() {
+        assert_eq!(centiseconds(0), 0);
+        assert_eq!(centiseconds(1), 2);
+        assert_eq!(centiseconds(2), 3);
+        assert_eq!(centiseconds(6), 10);
+        assert_eq!(centiseconds(60), 100);
+    }
+
+    #[test]
+    fn frames_too_short_to_show_are_replaced_by_the_next() {
+        let path = temp_path("held.gif");
+        let palette = Palette::default();
+        let mut recorder = Recorder::start(path.to_str().unwrap(), &palette, None, 1).unwrap();
+
+        let mut framebuffer = Framebuffer::new();
+        recorder.capture(&framebuffer, &palette, false).unwrap();
+
+        // Shown for 2 hundredths, long enough to keep
+        framebuffer.draw_sprite(0, 0, &[0x80]);
+        recorder.capture(&framebuffer, &palette, false).unwrap();
+
+        // Shown for 1 hundredth, so merged into the frame after it
+        framebuffer.draw_sprite(8, 0, &[0x80]);
+        for _ in 0..8 {
+            recorder.capture(&framebuffer, &palette, false).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+
+        let mut frames = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let lit = frame.buffer.iter().filter(|pixel| **pixel != 0).count();
+            frames.push((frame.delay, lit));
+        }
+        fs::remove_file(&path).unwrap();
+
+        // Low resolution pixels are doubled into the high resolution frame
+        assert_eq!(frames, vec![(2, 0), (centiseconds(10) as u16 - 2, 8)]);
+    }
+
+    #[test]
+    fn wav_headers_give_the_length_of_the_samples() {
+        let path = temp_path("sound.wav");
+        let mut wav = Wav::create(&path).unwrap();
+        wav.write_frame(0, true).unwrap();
+        wav.write_frame(1, false).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let field = |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        let data_size = 2 * 2 * SAMPLE_RATE / FRAME_RATE as u32;
+        assert_eq!(bytes.len() as u32, WAV_HEADER_SIZE + data_size);
+        assert_eq!(field(4), bytes.len() as u32 - 8);
+        assert_eq!(field(40), data_size);
+        assert_eq!(&bytes[36..40], b"data");
+    }
+}
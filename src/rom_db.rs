@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::platform::{Platform, Quirks};
+
+const BUILTIN: &str = include_str!("roms.json");
+
+/// Settings known to work for a particular ROM.
+#[derive(Clone, Default)]
+pub struct RomEntry {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    /// Instructions executed per 60 Hz frame.
+    pub speed: Option<u32>,
+    /// CHIP-8 keys paired with the names of the keyboard keys which should press them.
+    pub keys: Vec<(u8, String)>,
+    /// Hex colours, background first.
+    pub palette: Vec<String>,
+}
+
+/// ROM settings keyed by the SHA-1 of the ROM, in the same spirit as the community chip-8-database.
+///
+/// Stored as a JSON object of hashes to entries, e.g.
+/// `{"<sha1>": {"title": "Pong", "platform": "chip8", "speed": 15, "keys": {"1": "Up"}, "palette": ["#000000", "#ffffff"]}}`.
+/// Quirks are given as an object of `Quirks` field names, applied on top of the platform's preset.
+#[derive(Default)]
+pub struct RomDatabase {
+    entries: HashMap<String, RomEntry>,
+}
+
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+/// Where users can keep their own entries without passing `--rom-db` every time.
+pub fn user_database_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(".config/alvin/roms.json"))
+}
+
+impl RomDatabase {
+    /// The database shipped with alvin, extended by the user's own database if they have one.
+    pub fn load(extra: Option<&Path>) -> io::Result<RomDatabase> {
+        let mut database = RomDatabase::default();
+        database.extend(BUILTIN).expect("the builtin ROM database is invalid");
+
+        if let Some(path) = user_database_path().filter(|path| path.exists()) {
+            database.extend_from_file(&path)?;
+        }
+
+        if let Some(path) = extra {
+            database.extend_from_file(path)?;
+        }
+
+        Ok(database)
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomEntry> {
+        self.entries.get(&sha1(rom))
+    }
+
+    fn extend_from_file(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        self.extend(&contents).map_err(|message| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+        })
+    }
+
+    /// Adds the entries in `contents`, replacing any already known for the same hash.
+    fn extend(&mut self, contents: &str) -> Result<(), String> {
+        let database: Value = serde_json::from_str(contents).map_err(|error| error.to_string())?;
+        let entries = database.as_object().ok_or("expected an object of SHA-1 hashes to entries")?;
+
+        for (hash, entry) in entries.iter() {
+            let entry = parse_entry(entry).map_err(|message| format!("{}: {}", hash, message))?;
+            self.entries.insert(hash.to_ascii_lowercase(), entry);
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_entry(value: &Value) -> Result<RomEntry, String> {
+    let string = |key: &str| value[key].as_str().map(str::to_string);
+
+    let platform = match value["platform"].as_str() {
+        Some(name) => Some(Platform::from_name(name).ok_or_else(|| format!("unknown platform `{}`", name))?),
+        None => None,
+    };
+
+    let quirks = match value["quirks"].as_object() {
+        Some(overrides) => {
+            let mut quirks = platform.unwrap_or(Platform::Chip8).quirks();
+
+            for (name, enabled) in overrides.iter() {
+                let enabled = enabled.as_bool().ok_or_else(|| format!("quirk `{}` must be true or false", name))?;
+                let quirk = match name.as_str() {
+                    "shift_uses_vy" => &mut quirks.shift_uses_vy,
+                    "load_store_increments_i" => &mut quirks.load_store_increments_i,
+                    "jump_uses_vx" => &mut quirks.jump_uses_vx,
+                    "logic_resets_vf" => &mut quirks.logic_resets_vf,
+                    "wrap_sprites" => &mut quirks.wrap_sprites,
+                    _ => return Err(format!("unknown quirk `{}`", name)),
+                };
+
+                *quirk = enabled;
+            }
+
+            Some(quirks)
+        }
+        None => None,
+    };
+
+    let mut keys = vec![];
+    if let Some(mapping) = value["keys"].as_object() {
+        for (key, keyboard) in mapping.iter() {
+            let key = u8::from_str_radix(key, 16).ok()
+                .filter(|key| *key <= 0xF)
+                .ok_or_else(|| format!("`{}` is not a CHIP-8 key", key))?;
+            let keyboard = keyboard.as_str().ok_or_else(|| format!("key {:X} must map to a key name", key))?;
+
+            keys.push((key, keyboard.to_string()));
+        }
+    }
+
+    let palette = value["palette"].as_array()
+        .map(|colors| colors.iter().filter_map(Value::as_str).map(str::to_string).collect())
+        .unwrap_or_default();
+
+    Ok(RomEntry {
+        title: string("title"),
+        author: string("author"),
+        description: string("description"),
+        platform,
+        quirks,
+        speed: value["speed"].as_u64().map(|speed| speed as u32),
+        keys,
+        palette,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IBM_LOGO: [u8; 132] = [
+        0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x70, 0x09, 0xA2, 0x39, 0xD0, 0x1F,
+        0xA2, 0x48, 0x70, 0x08, 0xD0, 0x1F, 0x70, 0x04, 0xA2, 0x57, 0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x66,
+        0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x75, 0xD0, 0x1F, 0x12, 0x28, 0xFF, 0x00, 0xFF, 0x00, 0x3C, 0x00,
+        0x3C, 0x00, 0x3C, 0x00, 0x3C, 0x00, 0xFF, 0x00, 0xFF, 0xFF, 0x00, 0xFF, 0x00, 0x38, 0x00, 0x3F,
+        0x00, 0x3F, 0x00, 0x38, 0x00, 0xFF, 0x00, 0xFF, 0x80, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0x00,
+        0x80, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0xF8, 0x00, 0xFC, 0x00, 0x3E, 0x00, 0x3F, 0x00, 0x3B,
+        0x00, 0x39, 0x00, 0xF8, 0x00, 0xF8, 0x03, 0x00, 0x07, 0x00, 0x0F, 0x00, 0xBF, 0x00, 0xFB, 0x00,
+        0xF3, 0x00, 0xE3, 0x00, 0x43, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80,
+        0x00, 0xE0, 0x00, 0xE0,
+    ];
+
+    fn builtin() -> RomDatabase {
+        let mut database = RomDatabase::default();
+        database.extend(BUILTIN).unwrap();
+        database
+    }
+
+    #[test]
+    fn the_builtin_database_recognises_the_ibm_logo() {
+        let database = builtin();
+        let entry = database.lookup(&IBM_LOGO).expect("the IBM logo is missing from the builtin database");
+
+        assert_eq!(entry.title.as_deref(), Some("IBM Logo"));
+        assert!(entry.platform == Some(Platform::Chip8));
+        assert!(entry.quirks == Some(Platform::Chip8.quirks()));
+    }
+
+    #[test]
+    fn unknown_roms_have_no_entry() {
+        assert!(builtin().lookup(&IBM_LOGO[..IBM_LOGO.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn quirks_are_applied_over_the_platform_preset() {
+        let hash = sha1(&IBM_LOGO);
+        let mut database = builtin();
+        database.extend(&format!(
+            r#"{{"{}": {{"platform": "schip", "speed": 30, "quirks": {{"jump_uses_vx": false}}, "keys": {{"a": "Space"}}}}}}"#,
+            hash.to_ascii_uppercase(),
+        )).unwrap();
+
+        let entry = database.lookup(&IBM_LOGO).unwrap();
+        let mut expected = Platform::SuperChip.quirks();
+        expected.jump_uses_vx = false;
+
+        assert!(entry.quirks == Some(expected));
+        assert_eq!(entry.speed, Some(30));
+        assert_eq!(entry.keys, vec![(0xA, "Space".to_string())]);
+        assert_eq!(entry.title, None, "entries for the same hash replace the builtin one");
+    }
+
+    #[test]
+    fn unknown_quirks_and_platforms_are_rejected() {
+        let mut database = RomDatabase::default();
+
+        assert!(database.extend(r#"{"00": {"quirks": {"fast_draw": true}}}"#).is_err());
+        assert!(database.extend(r#"{"00": {"platform": "chip48"}}"#).is_err());
+        assert!(database.extend(r#"{"00": {"keys": {"10": "Space"}}}"#).is_err());
+    }
+}
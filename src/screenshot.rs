@@ -0,0 +1,132 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    framebuffer::Framebuffer,
+    palette::Palette,
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Saves the framebuffer in `palette` to the current directory twice: at its own resolution, and
+/// with every pixel `scale` pixels across as it's shown in the window.
+///
+/// The files are named after `name` and the time, down to the millisecond so screenshots taken in
+/// quick succession don't overwrite each other.
+pub fn save(framebuffer: &Framebuffer, palette: &Palette, scale: usize, name: &str) -> Result<Vec<PathBuf>, String> {
+    let stem = format!("{}-{}", name, timestamp(SystemTime::now()));
+    let mut paths = vec![PathBuf::from(format!("{}.png", stem))];
+    if scale > 1 {
+        paths.push(PathBuf::from(format!("{}-{}x.png", stem, scale)));
+    }
+
+    for (path, scale) in paths.iter().zip([1, scale].iter()) {
+        write_png(framebuffer, palette, *scale, path)
+            .map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+    }
+
+    Ok(paths)
+}
+
+fn write_png(framebuffer: &Framebuffer, palette: &Palette, scale: usize, path: &Path) -> Result<(), String> {
+    let (width, height) = framebuffer.dimensions();
+    let pixels = framebuffer.pixels();
+
+    let mut data = Vec::with_capacity(width * height * scale * scale * 3);
+    for y in 0..height * scale {
+        for x in 0..width * scale {
+            let color = palette.color(pixels[(y / scale) * width + x / scale]);
+            data.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), (width * scale) as u32, (height * scale) as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+    writer.write_image_data(&data).map_err(|error| error.to_string())
+}
+
+/// `time` in UTC as `YYYYMMDD-HHMMSS-mmm`.
+pub fn timestamp(time: SystemTime) -> String {
+    let now = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = (now.as_secs() / SECONDS_PER_DAY, now.as_secs() % SECONDS_PER_DAY);
+
+    // Howard Hinnant's civil_from_days, with years starting in March so leap days come last
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year, month, day,
+        seconds / 3600, seconds / 60 % 60, seconds % 60,
+        now.subsec_millis(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Duration};
+
+    use super::*;
+
+    fn at(seconds: u64, millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn timestamps_count_days_through_leap_years() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101-000000-000");
+        assert_eq!(timestamp(at(951_782_400 + 45_296, 789)), "20000229-123456-789");
+        // 2100 is not a leap year, so February ends on the 28th
+        assert_eq!(timestamp(at(4_107_542_400 - 1, 999)), "21000228-235959-999");
+        assert_eq!(timestamp(at(4_107_542_400, 0)), "21000301-000000-000");
+    }
+
+    #[test]
+    fn scaled_screenshots_repeat_each_pixel_in_the_palette_colours() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(0, 0, &[0x80]);
+        let palette = Palette::default();
+
+        let path = env::temp_dir().join(format!("alvin-screenshot-{}.png", process::id()));
+        write_png(&framebuffer, &palette, 2, &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((info.width, info.height), (128, 64));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+
+        let pixel = |x: usize, y: usize| {
+            let offset = (y * 128 + x) * 3;
+            (data[offset], data[offset + 1], data[offset + 2])
+        };
+        let rgb = |planes: u8| {
+            let color = palette.color(planes);
+            (color.r, color.g, color.b)
+        };
+
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            assert_eq!(pixel(*x, *y), rgb(1));
+        }
+        assert_eq!(pixel(2, 0), rgb(0));
+        assert_eq!(pixel(0, 2), rgb(0));
+    }
+}
@@ -0,0 +1,229 @@
+use sdl2::pixels::Color;
+
+/// Pixel art scaling filters run on the CPU before the screen is handed to SDL, which then
+/// scales the result by a whole number with nearest neighbour sampling.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Upscaler {
+    Nearest,
+    /// Scale2x, also known as EPX, which rounds off diagonal staircases.
+    Scale2x,
+    Scale3x,
+    /// Scale2x applied twice, then the edges it leaves blended with their neighbours, after the
+    /// smooth but unstepped look of the hqx filters.
+    Hq,
+    /// Each pixel drawn 3x3 with a dark scanline under it and a red, green and blue aperture
+    /// grille across it.
+    Crt,
+}
+
+impl Upscaler {
+    pub fn from_name(name: &str) -> Option<Upscaler> {
+        match name {
+            "nearest" => Some(Upscaler::Nearest),
+            "scale2x" => Some(Upscaler::Scale2x),
+            "scale3x" => Some(Upscaler::Scale3x),
+            "hq" => Some(Upscaler::Hq),
+            "crt" => Some(Upscaler::Crt),
+            _ => None,
+        }
+    }
+
+    pub fn factor(self) -> usize {
+        match self {
+            Upscaler::Nearest => 1,
+            Upscaler::Scale2x => 2,
+            Upscaler::Scale3x | Upscaler::Crt => 3,
+            Upscaler::Hq => 4,
+        }
+    }
+
+    /// Scales a `width` by `height` image by `factor()`.
+    pub fn apply(self, image: &[Color], width: usize, height: usize) -> Vec<Color> {
+        match self {
+            Upscaler::Nearest => image.to_vec(),
+            Upscaler::Scale2x => scale2x(image, width, height),
+            Upscaler::Scale3x => scale3x(image, width, height),
+            Upscaler::Hq => {
+                let scaled = scale2x(&scale2x(image, width, height), width * 2, height * 2);
+                smooth_edges(&scaled, width * 4, height * 4)
+            }
+            Upscaler::Crt => crt(image, width, height),
+        }
+    }
+}
+
+/// The pixel at `(x, y)` offset by `(dx, dy)`, repeating the edge pixels past the edges.
+fn neighbour(image: &[Color], width: usize, height: usize, x: usize, y: usize, dx: isize, dy: isize) -> Color {
+    let x = (x as isize + dx).max(0).min(width as isize - 1) as usize;
+    let y = (y as isize + dy).max(0).min(height as isize - 1) as usize;
+    image[y * width + x]
+}
+
+fn scale2x(image: &[Color], width: usize, height: usize) -> Vec<Color> {
+    let mut scaled = vec![Color::RGB(0, 0, 0); width * height * 4];
+    let scaled_width = width * 2;
+
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx, dy| neighbour(image, width, height, x, y, dx, dy);
+            let (b, d, e, f, h) = (at(0, -1), at(-1, 0), at(0, 0), at(1, 0), at(0, 1));
+
+            let (e0, e1, e2, e3) = if b != h && d != f {
+                (
+                    if d == b { d } else { e },
+                    if b == f { f } else { e },
+                    if d == h { d } else { e },
+                    if h == f { f } else { e },
+                )
+            } else {
+                (e, e, e, e)
+            };
+
+            let top = y * 2 * scaled_width + x * 2;
+            scaled[top] = e0;
+            scaled[top + 1] = e1;
+            scaled[top + scaled_width] = e2;
+            scaled[top + scaled_width + 1] = e3;
+        }
+    }
+
+    scaled
+}
+
+fn scale3x(image: &[Color], width: usize, height: usize) -> Vec<Color> {
+    let mut scaled = vec![Color::RGB(0, 0, 0); width * height * 9];
+    let scaled_width = width * 3;
+
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx, dy| neighbour(image, width, height, x, y, dx, dy);
+            let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+            let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+            let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if (d == b && e != c) || (b == f && e != a) { b } else { e },
+                    if b == f { f } else { e },
+                    if (d == b && e != g) || (d == h && e != a) { d } else { e },
+                    e,
+                    if (b == f && e != i) || (h == f && e != c) { f } else { e },
+                    if d == h { d } else { e },
+                    if (d == h && e != i) || (h == f && e != g) { h } else { e },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 9]
+            };
+
+            for (index, color) in block.iter().enumerate() {
+                scaled[(y * 3 + index / 3) * scaled_width + x * 3 + index % 3] = *color;
+            }
+        }
+    }
+
+    scaled
+}
+
+/// Blends pixels on an edge with their neighbours, leaving flat areas untouched.
+fn smooth_edges(image: &[Color], width: usize, height: usize) -> Vec<Color> {
+    const WEIGHTS: [(isize, isize, u32); 5] = [(0, 0, 4), (0, -1, 1), (-1, 0, 1), (1, 0, 1), (0, 1, 1)];
+
+    let mut smoothed = image.to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            let center = image[y * width + x];
+            let samples: Vec<(Color, u32)> = WEIGHTS.iter()
+                .map(|(dx, dy, weight)| (neighbour(image, width, height, x, y, *dx, *dy), *weight))
+                .collect();
+
+            if samples.iter().all(|(color, _)| *color == center) {
+                continue;
+            }
+
+            let total: u32 = samples.iter().map(|(_, weight)| weight).sum();
+            let channel = |pick: fn(Color) -> u8| {
+                (samples.iter().map(|(color, weight)| pick(*color) as u32 * weight).sum::<u32>() / total) as u8
+            };
+
+            smoothed[y * width + x] = Color::RGB(channel(|color| color.r), channel(|color| color.g), channel(|color| color.b));
+        }
+    }
+
+    smoothed
+}
+
+fn crt(image: &[Color], width: usize, height: usize) -> Vec<Color> {
+    // Brightness in percent of each row of a pixel, and of each colour channel across it
+    const SCANLINE: [u32; 3] = [100, 100, 45];
+    const GRILLE: [[u32; 3]; 3] = [[100, 80, 80], [80, 100, 80], [80, 80, 100]];
+
+    let mut scaled = vec![Color::RGB(0, 0, 0); width * height * 9];
+    let scaled_width = width * 3;
+
+    for (index, color) in image.iter().enumerate() {
+        let (x, y) = (index % width, index / width);
+
+        for (row, scanline) in SCANLINE.iter().enumerate() {
+            for (column, grille) in GRILLE.iter().enumerate() {
+                let dim = |channel: u8, grille: u32| (channel as u32 * scanline * grille / 10_000) as u8;
+                scaled[(y * 3 + row) * scaled_width + x * 3 + column] =
+                    Color::RGB(dim(color.r, grille[0]), dim(color.g, grille[1]), dim(color.b, grille[2]));
+            }
+        }
+    }
+
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ON: Color = Color { r: 0xFF, g: 0xFF, b: 0xFF, a: 0xFF };
+    const OFF: Color = Color { r: 0, g: 0, b: 0, a: 0xFF };
+
+    /// An image drawn with `X` for lit pixels and `.` for unlit ones.
+    fn image(rows: &[&str]) -> Vec<Color> {
+        rows.iter()
+            .flat_map(|row| row.chars().map(|pixel| if pixel == 'X' { ON } else { OFF }))
+            .collect()
+    }
+
+    #[test]
+    fn scale2x_rounds_off_diagonals() {
+        let diagonal = image(&["X.", ".X"]);
+        assert_eq!(scale2x(&diagonal, 2, 2), image(&[
+            "XX..",
+            "X.X.",
+            ".X.X",
+            "..XX",
+        ]));
+    }
+
+    #[test]
+    fn scale3x_rounds_off_diagonals() {
+        let diagonal = image(&["X.", ".X"]);
+        assert_eq!(scale3x(&diagonal, 2, 2), image(&[
+            "XXX...",
+            "XX.X..",
+            "X..XX.",
+            ".XX..X",
+            "..X.XX",
+            "...XXX",
+        ]));
+    }
+
+    #[test]
+    fn upscalers_grow_images_by_their_factor() {
+        let (width, height) = (64, 32);
+        let blank = vec![OFF; width * height];
+
+        for name in ["nearest", "scale2x", "scale3x", "hq", "crt"].iter() {
+            let upscaler = Upscaler::from_name(name).unwrap();
+            let factor = upscaler.factor();
+            assert_eq!(upscaler.apply(&blank, width, height).len(), width * height * factor * factor, "{}", name);
+        }
+    }
+}